@@ -1,23 +1,179 @@
-use lib::client::{configuration::ClientConfiguration, process};
-use log::info;
+use std::{future::Future, path::Path, process::ExitCode, sync::Arc, time::Duration};
+
+use clap::{Parser, Subcommand};
+use lib::{client::{console::ConsoleFormat, configuration::{ClientConfiguration, LogConfiguration, ServerEndpoint, CONFIGURATION_FILE, DEFAULT_SHUTDOWN_GRACE_PERIOD}, context::ClientContext, process, shutdown::{self, Shutdown}, status, supervisor, telemetry}, Applicatiton, MultiPodApplication};
+use log::{error, info, warn};
+use tokio::time;
+
+#[derive(Parser)]
+#[command(name = "fefs client", about = "Tails log files and sends them to fefs servers")]
+struct Cli {
+    /// Configuration file to use
+    #[arg(long, env = "FEFS_CONFIG", default_value = CONFIGURATION_FILE, global = true)]
+    config: String,
+
+    /// Print what would be sent to stdout instead of connecting to servers
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// How a dry run prints messages, json or human
+    #[arg(long, default_value = "json", global = true)]
+    output: ConsoleFormat,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Tail every log in the configuration file (the default)
+    Run,
+    /// Check the configuration file and exit
+    Validate,
+    /// Print an example configuration file
+    PrintDefaultConfig,
+    /// Tail a single file without a configuration file
+    Tail {
+        /// Log file to tail
+        #[arg(long)]
+        file: String,
+        /// Application name to publish the file as
+        #[arg(long)]
+        app: String,
+        /// Pod name, publishes the file as a multi pod application
+        #[arg(long)]
+        pod: Option<String>,
+        /// Server to send to, as ws://host:port/path, wss://host:port/path or host:port/path, not needed for a dry run
+        #[arg(long)]
+        server: Option<ServerEndpoint>,
+    },
+}
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
-    let client_configuration = ClientConfiguration::read_from_file();
-    let mut process_thread_handlers = vec![];
+    let cli = Cli::parse();
+    let console = cli.dry_run.then_some(cli.output);
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => run(cli.config, console).await,
+        Command::Validate => validate(&cli.config),
+        Command::PrintDefaultConfig => {
+            println!("{}", serde_json::to_string_pretty(&ClientConfiguration::default()).unwrap());
+            ExitCode::SUCCESS
+        }
+        Command::Tail { file, app, pod, server } => tail(file, app, pod, server, console).await,
+    }
+}
 
-    for config in client_configuration.get_configurations() {
-        let handler = tokio::spawn(async move {
-            process::file(config).await;
-        });
-        process_thread_handlers.push(handler);
+// a dry run neither serves the status nor loads or saves checkpoints, so it doesn't disturb a real client
+async fn run(config_path: String, console: Option<ConsoleFormat>) -> ExitCode {
+    let client_configuration = match ClientConfiguration::load(&config_path) {
+        Ok(client_configuration) => client_configuration,
+        Err(e) => {
+            error!("Cannot start with {}: {}", config_path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let statuses = Arc::new(status::new_statuses());
+    let tailers = Arc::new(telemetry::new_tailers());
+    let (shutdown_tx, shutdown) = Shutdown::new();
+    let context = match console {
+        Some(format) => ClientContext::new(statuses, tailers, shutdown, None).with_console(format),
+        None => {
+            if let Some(status_address) = client_configuration.get_status_address() {
+                status::serve(status_address, Arc::clone(&statuses), Arc::clone(&tailers), client_configuration.get_max_disconnected());
+            }
+            ClientContext::new(statuses, tailers, shutdown, client_configuration.get_checkpoint_dir())
+        }
+    };
+    let connections = Arc::clone(context.get_connections());
+    let shutdown_grace_period = client_configuration.get_shutdown_grace_period();
+    let work = async move {
+        supervisor::supervise(config_path, client_configuration, context).await;
+        connections.closed().await;
+    };
+    run_until_signal(work, shutdown_tx, shutdown_grace_period).await
+}
+
+// on SIGTERM or SIGINT the work is told to stop and gets the grace period to flush before the client exits anyway
+async fn run_until_signal(work: impl Future<Output = ()> + Send + 'static, shutdown_tx: tokio::sync::watch::Sender<bool>, grace_period: Duration) -> ExitCode {
+    let mut work = tokio::spawn(work);
+    tokio::select! {
+        _ = shutdown::signal() => {},
+        _ = &mut work => {
+            info!("fefs client stopped");
+            return ExitCode::SUCCESS;
+        }
+    }
+
+    info!("shutting down, waiting up to {:?} for tailers to flush", grace_period);
+    let _ = shutdown_tx.send(true);
+    match time::timeout(grace_period, work).await {
+        Ok(_) => info!("fefs client stopped"),
+        Err(_) => warn!("fefs client stopped after the {:?} grace period, some messages may not have been delivered", grace_period),
     }
+    ExitCode::SUCCESS
+}
 
-    for handler in process_thread_handlers {
-        handler.await.unwrap();
+fn validate(config_path: &str) -> ExitCode {
+    match ClientConfiguration::load(config_path) {
+        Ok(client_configuration) if client_configuration.get_issues().is_empty() => {
+            println!("{} is valid, {} log configuration(s)", config_path, client_configuration.get_configurations().len());
+            ExitCode::SUCCESS
+        }
+        Ok(client_configuration) => {
+            println!("{} has {} problem(s):", config_path, client_configuration.get_issues().len());
+            for issue in client_configuration.get_issues() {
+                println!("  {}", issue);
+            }
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            println!("{} is invalid: {}", config_path, e);
+            ExitCode::FAILURE
+        }
     }
+}
+
+async fn tail(file: String, app: String, pod: Option<String>, server: Option<ServerEndpoint>, console: Option<ConsoleFormat>) -> ExitCode {
+    let servers = match (server, console) {
+        (Some(server), _) => vec![server],
+        (None, Some(_)) => vec![],
+        (None, None) => {
+            error!("--server is needed unless it's a dry run");
+            return ExitCode::FAILURE;
+        }
+    };
+    let path = Path::new(&file);
+    let file_name = match path.file_name().and_then(|file_name| file_name.to_str()) {
+        Some(file_name) => file_name,
+        None => {
+            error!("{} is not a file", file);
+            return ExitCode::FAILURE;
+        }
+    };
+    let dir = match path.parent().and_then(Path::to_str) {
+        Some(dir) if !dir.is_empty() => dir.to_string(),
+        _ => ".".to_string(),
+    };
+    let regex = format!("^{}$", regex::escape(file_name));
+    let application = match pod {
+        Some(pod) => Applicatiton::MultiPod(MultiPodApplication::new(app, pod)),
+        None => Applicatiton::SinglePod(app),
+    };
 
-    info!("fefs client stopped");
-}
\ No newline at end of file
+    let config = LogConfiguration::new(application, dir, regex, servers);
+    let (shutdown_tx, shutdown) = Shutdown::new();
+    let mut context = ClientContext::new(Arc::new(status::new_statuses()), Arc::new(telemetry::new_tailers()), shutdown, None);
+    if let Some(format) = console {
+        context = context.with_console(format);
+    }
+    let connections = Arc::clone(context.get_connections());
+    let work = async move {
+        process::file(config, context).await;
+        connections.closed().await;
+    };
+    run_until_signal(work, shutdown_tx, DEFAULT_SHUTDOWN_GRACE_PERIOD).await
+}
@@ -1,10 +1,11 @@
 use actix_web::{get, middleware, web, App, HttpResponse, HttpServer, Responder};
 use actix_files as fs;
 use actix_cors::Cors;
-use lib::server::broadcaster;
-use log::info;
+use actix_tls::accept::rustls_0_23::TlsStream;
+use lib::{server::{auth::{Authenticator, ClientCertificate}, broadcaster, requests::Requests}, tls};
+use log::{info, warn};
 use serde::Serialize;
-use std::{env, sync::Arc};
+use std::{any::Any, env, sync::Arc};
 
 #[derive(Serialize)]
 struct ApiResponse {
@@ -28,13 +29,33 @@ async fn main() -> std::io::Result<()> {
     let frontend_origin = env::var("FRONTEND_ORIGIN")
         .unwrap_or_else(|_| "http://localhost:5173".to_string());
     
-    info!("starting at http://{}:{}", host, port);
+    // serve https and wss when a certificate and key are given
+    let tls_config = match (env::var("TLS_CERT_PATH"), env::var("TLS_KEY_PATH")) {
+        (Ok(cert_path), Ok(key_path)) => {
+            let client_ca_path = env::var("TLS_CLIENT_CA_PATH").ok();
+            Some(tls::server_config(&cert_path, &key_path, client_ca_path.as_deref())?)
+        },
+        _ => None,
+    };
+    let scheme = if tls_config.is_some() { "https" } else { "http" };
+
+    info!("starting at {}://{}:{}", scheme, host, port);
     info!("frontend origin: {}", frontend_origin);
 
     let broadcasters = broadcaster::new_broadcasters();
     let broadcasters = Arc::new(broadcasters);
 
-    HttpServer::new(move || {
+    let requests = Arc::new(Requests::new());
+
+    let authenticator = Arc::new(Authenticator::from_env()?);
+    if !authenticator.is_enabled() {
+        warn!("no AUTH_TOKENS, AUTH_JWT_SECRET or TLS_CLIENT_APPLICATIONS_PATH set, any client can publish any application");
+    }
+    if authenticator.is_certificate_enabled() && env::var("TLS_CLIENT_CA_PATH").is_err() {
+        warn!("TLS_CLIENT_APPLICATIONS_PATH is set without TLS_CLIENT_CA_PATH, no client will be able to publish");
+    }
+
+    let server = HttpServer::new(move || {
         // CORS configuration for development
         let cors = Cors::default()
             .allowed_origin(&frontend_origin)
@@ -43,17 +64,29 @@ async fn main() -> std::io::Result<()> {
             .supports_credentials();
         let broadcasters = Arc::clone(&broadcasters);
         let broadcasters = web::Data::new(broadcasters);
+        let authenticator = web::Data::new(Arc::clone(&authenticator));
+        let requests = web::Data::new(Arc::clone(&requests));
 
         App::new()
             .wrap(middleware::Logger::default())
             .wrap(cors)
             .app_data(broadcasters)
+            .app_data(authenticator)
+            .app_data(requests)
             // API routes
             .service(hello)
             // WebSocket route
             .service(lib::server::data_inbound)
             // SSE route
             .service(lib::server::data_outbound)
+            // file routes
+            .service(lib::server::files::list_files)
+            .service(lib::server::files::switch_file)
+            .service(lib::server::files::stream_file)
+            // search route
+            .service(lib::server::search::search)
+            // stats route
+            .service(lib::server::stats::get_stats)
             // In production, serve the built frontend
             .service(
                 fs::Files::new("/", "./frontend/build")
@@ -68,7 +101,22 @@ async fn main() -> std::io::Result<()> {
                     })
             )
     })
-    .bind(format!("{}:{}", host, port))?
-    .run()
-    .await
-}
\ No newline at end of file
+    .on_connect(store_client_certificate);
+
+    let address = format!("{}:{}", host, port);
+    let server = match tls_config {
+        Some(tls_config) => server.bind_rustls_0_23(address, tls_config)?,
+        None => server.bind(address)?,
+    };
+
+    server.run().await
+}
+
+fn store_client_certificate(connection: &dyn Any, data: &mut actix_web::dev::Extensions) {
+    if let Some(tls_stream) = connection.downcast_ref::<TlsStream<actix_web::rt::net::TcpStream>>() {
+        let (_, session) = tls_stream.get_ref();
+        if let Some(certificate) = session.peer_certificates().and_then(|certificates| certificates.first()) {
+            data.insert(ClientCertificate(certificate.clone().into_owned()));
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use log::info;
+use tokio::time::Instant;
+
+use crate::message::Message;
+
+use super::telemetry::{self, Counters};
+
+// what was read while the server had paused the application, so a viewer who opens it sees the
+// last lines instead of nothing, the oldest are dropped once it's full
+pub struct Backlog {
+    messages: VecDeque<(Instant, Message)>,
+    capacity: usize,
+    max_age: Duration,
+    dropped: u64,
+    counters: Arc<Counters>
+}
+
+impl Backlog {
+    pub fn new(capacity: usize, max_age: Duration, counters: Arc<Counters>) -> Self {
+        Self { messages: VecDeque::new(), capacity, max_age, dropped: 0, counters }
+    }
+
+    pub fn push(&mut self, msg: Message) {
+        if self.capacity == 0 {
+            self.drop_message(&msg);
+            return;
+        }
+        if self.messages.len() >= self.capacity {
+            if let Some((_, oldest)) = self.messages.pop_front() {
+                self.drop_message(&oldest);
+            }
+        }
+        self.messages.push_back((Instant::now(), msg));
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+
+    // the buffered messages that aren't older than max_age, oldest first
+    pub fn drain(&mut self) -> Vec<Message> {
+        if self.dropped > 0 {
+            info!("dropped {} lines while paused", self.dropped);
+            self.dropped = 0;
+        }
+        let now = Instant::now();
+        let max_age = self.max_age;
+        let (fresh, stale): (Vec<_>, Vec<_>) = self.messages.drain(..)
+            .map(|(buffered, msg)| (now.duration_since(buffered) <= max_age, msg))
+            .partition(|(fresh, _)| *fresh);
+        self.counters.dropped(stale.iter().filter(|(_, msg)| telemetry::is_line(msg)).count() as u64);
+        fresh.into_iter().map(|(_, msg)| msg).collect()
+    }
+
+    fn drop_message(&mut self, msg: &Message) {
+        self.dropped += 1;
+        if telemetry::is_line(msg) {
+            self.counters.dropped(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{message::DataMessage, Applicatiton};
+
+    use super::*;
+
+    fn line(row: &str) -> Message {
+        Message::Data(DataMessage::new(row.to_string(), Applicatiton::SinglePod("app".to_string()), false))
+    }
+
+    fn rows(messages: Vec<Message>) -> Vec<String> {
+        messages.iter().map(|msg| msg.data().unwrap().row().to_string()).collect()
+    }
+
+    #[test]
+    fn oldest_lines_are_evicted_once_full() {
+        let counters = Arc::new(Counters::default());
+        let mut backlog = Backlog::new(3, Duration::from_secs(60), Arc::clone(&counters));
+        for row in ["1", "2", "3", "4", "5"] {
+            backlog.push(line(row));
+        }
+        assert_eq!(backlog.len(), 3);
+        assert_eq!(rows(backlog.drain()), ["3", "4", "5"]);
+        assert!(backlog.is_empty());
+        assert_eq!(counters.get_lines_dropped(), 2);
+    }
+
+    #[test]
+    fn nothing_is_kept_without_capacity() {
+        let mut backlog = Backlog::new(0, Duration::from_secs(60), Arc::default());
+        backlog.push(line("1"));
+        assert!(backlog.is_empty());
+        assert!(backlog.drain().is_empty());
+    }
+
+    #[tokio::test]
+    async fn old_lines_are_not_sent() {
+        let counters = Arc::new(Counters::default());
+        let mut backlog = Backlog::new(10, Duration::from_millis(200), Arc::clone(&counters));
+        backlog.push(line("old"));
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        backlog.push(line("new"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(rows(backlog.drain()), ["new"]);
+        assert_eq!(counters.get_lines_dropped(), 1);
+    }
+}
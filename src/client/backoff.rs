@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct BackoffConfiguration {
+    initial_delay_ms: u64,
+    max_delay_ms: u64,
+    multiplier: f64,
+    // fraction of the delay that is randomised, 0.0 disables jitter and 1.0 is full jitter
+    jitter: f64
+}
+
+impl Default for BackoffConfiguration {
+    fn default() -> Self {
+        Self { initial_delay_ms: 1_000, max_delay_ms: 60_000, multiplier: 2.0, jitter: 0.5 }
+    }
+}
+
+impl BackoffConfiguration {
+    pub fn get_initial_delay(&self) -> Duration {
+        Duration::from_millis(self.initial_delay_ms)
+    }
+
+    pub fn get_max_delay(&self) -> Duration {
+        Duration::from_millis(self.max_delay_ms)
+    }
+
+    pub fn get_multiplier(&self) -> f64 {
+        self.multiplier
+    }
+
+    pub fn get_jitter(&self) -> f64 {
+        self.jitter
+    }
+}
+
+pub struct Backoff {
+    config: BackoffConfiguration,
+    attempt: u32
+}
+
+impl Backoff {
+    pub fn new(config: BackoffConfiguration) -> Self {
+        Self { config, attempt: 0 }
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let initial = self.config.initial_delay_ms as f64;
+        let max = self.config.max_delay_ms as f64;
+        // past i32::MAX attempts the exponent would wrap negative
+        let exponent = self.attempt.min(i32::MAX as u32) as i32;
+        let delay = (initial * self.config.multiplier.max(1.0).powi(exponent)).min(max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let jitter = self.config.jitter.clamp(0.0, 1.0);
+        let delay = delay - delay * jitter * rand::thread_rng().gen::<f64>();
+        Duration::from_millis(delay as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backoff(initial_delay_ms: u64, max_delay_ms: u64, multiplier: f64, jitter: f64) -> Backoff {
+        Backoff::new(BackoffConfiguration { initial_delay_ms, max_delay_ms, multiplier, jitter })
+    }
+
+    #[test]
+    fn grows_up_to_the_max() {
+        let mut backoff = backoff(100, 1_000, 2.0, 0.0);
+        let delays: Vec<u64> = (0..6).map(|_| backoff.next_delay().as_millis() as u64).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1_000, 1_000]);
+        assert_eq!(backoff.attempt(), 6);
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut backoff = backoff(100, 1_000, 3.0, 0.0);
+        backoff.next_delay();
+        backoff.next_delay();
+        backoff.reset();
+        assert_eq!(backoff.attempt(), 0);
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn multiplier_below_one_never_shrinks() {
+        let mut backoff = backoff(500, 1_000, 0.5, 0.0);
+        assert_eq!(backoff.next_delay(), Duration::from_millis(500));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn jitter_only_shortens() {
+        let mut backoff = backoff(1_000, 1_000, 2.0, 0.5);
+        for _ in 0..100 {
+            let delay = backoff.next_delay();
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_millis(1_000), "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn jitter_is_clamped() {
+        let mut backoff = backoff(1_000, 1_000, 2.0, 5.0);
+        for _ in 0..100 {
+            assert!(backoff.next_delay() <= Duration::from_millis(1_000));
+        }
+    }
+
+    #[test]
+    fn many_attempts_do_not_overflow() {
+        let mut backoff = backoff(1_000, 60_000, 2.0, 0.0);
+        backoff.attempt = u32::MAX - 1;
+        assert_eq!(backoff.next_delay(), Duration::from_millis(60_000));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(60_000));
+        assert_eq!(backoff.attempt(), u32::MAX);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Applicatiton;
+
+use super::identity::FileIdentity;
+
+// how far a file was read when the client stopped, so the next run carries on from there
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Checkpoint {
+    path: String,
+    // checkpoints written before identities were tracked have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    identity: Option<FileIdentity>,
+    position: u64
+}
+
+impl Checkpoint {
+    pub fn new(path: String, identity: Option<FileIdentity>, position: u64) -> Self {
+        Self { path, identity, position }
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_identity(&self) -> Option<FileIdentity> {
+        self.identity
+    }
+
+    pub fn get_position(&self) -> u64 {
+        self.position
+    }
+}
+
+// one file per application, named after it with anything unsafe for a file name replaced
+fn checkpoint_file(dir: &str, application: &Applicatiton) -> PathBuf {
+    let name = match application.pod_name() {
+        Some(pod_name) => format!("{}__{}", application.name(), pod_name),
+        None => application.name(),
+    };
+    let name: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect();
+    PathBuf::from(dir).join(format!("{}.checkpoint.json", name))
+}
+
+pub fn save(dir: &str, application: &Applicatiton, checkpoint: &Checkpoint) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = checkpoint_file(dir, application);
+    // written next to the real file and renamed over it, so a crash never leaves half a checkpoint
+    let temporary = path.with_extension("json.tmp");
+    fs::write(&temporary, serde_json::to_vec(checkpoint)?)?;
+    fs::rename(temporary, path)
+}
+
+pub fn load(dir: &str, application: &Applicatiton) -> Option<Checkpoint> {
+    let checkpoint = fs::read(checkpoint_file(dir, application)).ok()?;
+    serde_json::from_slice(&checkpoint).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let dir = env::temp_dir().join(format!("fefs-checkpoint-test-{}", std::process::id())).display().to_string();
+        let application: Applicatiton = serde_json::from_str(r#"{"MultiPod": {"application": "my/app", "pod_name": "pod 0"}}"#).unwrap();
+        assert_eq!(load(&dir, &application), None);
+
+        save(&dir, &application, &Checkpoint::new("app.log".to_string(), None, 42)).unwrap();
+        save(&dir, &application, &Checkpoint::new("app.log".to_string(), None, 84)).unwrap();
+        let loaded = load(&dir, &application);
+        let file_name = checkpoint_file(&dir, &application).file_name().unwrap().to_string_lossy().to_string();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded, Some(Checkpoint::new("app.log".to_string(), None, 84)));
+        assert_eq!(file_name, "my_app__pod_0.checkpoint.json");
+    }
+
+    #[test]
+    fn checkpoint_without_identity() {
+        let checkpoint: Checkpoint = serde_json::from_str(r#"{"path": "app.log", "position": 42}"#).unwrap();
+        assert_eq!(checkpoint, Checkpoint::new("app.log".to_string(), None, 42));
+        assert_eq!(serde_json::to_string(&checkpoint).unwrap(), r#"{"path":"app.log","position":42}"#);
+    }
+}
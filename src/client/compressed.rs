@@ -0,0 +1,159 @@
+use std::io::{self, BufRead};
+
+use flate2::read::MultiGzDecoder;
+use tokio::{fs::File, io::{AsyncBufReadExt, BufReader}, sync::mpsc, task};
+
+use super::error::ClientError;
+
+// lines decompressed ahead of whoever reads them
+const LINE_BUFFER: usize = 1024;
+
+// how logrotate compresses rotated files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileCompression {
+    Gzip,
+    Zstd
+}
+
+impl FileCompression {
+    // by the extension logrotate gives compressed files
+    pub fn of(path: &str) -> Option<Self> {
+        if path.ends_with(".gz") {
+            Some(FileCompression::Gzip)
+        } else if path.ends_with(".zst") {
+            Some(FileCompression::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+// reads a file from the start line by line, decompressing it on a blocking thread if it's compressed
+pub struct LineReader {
+    path: String,
+    lines: Lines
+}
+
+enum Lines {
+    Plain(BufReader<File>),
+    Decompressed(mpsc::Receiver<io::Result<Vec<u8>>>)
+}
+
+impl LineReader {
+    pub async fn open(path: &str) -> Result<Self, ClientError> {
+        let file = File::open(path).await
+            .map_err(|source| ClientError::Open { path: path.to_string(), source })?;
+        let lines = match FileCompression::of(path) {
+            Some(compression) => Lines::Decompressed(decompress(file.into_std().await, compression)),
+            None => Lines::Plain(BufReader::new(file)),
+        };
+        Ok(Self { path: path.to_string(), lines })
+    }
+
+    // the next line without its line ending, None at the end of the file
+    pub async fn next_line(&mut self) -> Result<Option<String>, ClientError> {
+        let bytes = match &mut self.lines {
+            Lines::Plain(reader) => {
+                let mut bytes = vec![];
+                match reader.read_until(b'\n', &mut bytes).await {
+                    Ok(0) => None,
+                    Ok(_) => Some(bytes),
+                    Err(source) => return Err(ClientError::Read { path: self.path.clone(), source }),
+                }
+            }
+            Lines::Decompressed(lines) => match lines.recv().await {
+                Some(Ok(bytes)) => Some(bytes),
+                Some(Err(source)) => return Err(ClientError::Read { path: self.path.clone(), source }),
+                None => None,
+            },
+        };
+        Ok(bytes.map(|bytes| String::from_utf8_lossy(&bytes).trim_end_matches(['\n', '\r']).to_string()))
+    }
+}
+
+// stops as soon as no one reads the lines anymore
+fn decompress(file: std::fs::File, compression: FileCompression) -> mpsc::Receiver<io::Result<Vec<u8>>> {
+    let (tx, rx) = mpsc::channel(LINE_BUFFER);
+    task::spawn_blocking(move || {
+        let decoder: Box<dyn io::Read> = match compression {
+            FileCompression::Gzip => Box::new(MultiGzDecoder::new(file)),
+            FileCompression::Zstd => match zstd::Decoder::new(file) {
+                Ok(decoder) => Box::new(decoder),
+                Err(e) => {
+                    let _ = tx.blocking_send(Err(e));
+                    return;
+                }
+            },
+        };
+        let mut reader = io::BufReader::new(decoder);
+        loop {
+            let mut bytes = vec![];
+            let line = match reader.read_until(b'\n', &mut bytes) {
+                Ok(0) => return,
+                Ok(_) => Ok(bytes),
+                Err(e) => Err(e),
+            };
+            let failed = line.is_err();
+            if tx.blocking_send(line).is_err() || failed {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Write};
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    async fn lines(path: &str) -> Vec<String> {
+        let mut reader = LineReader::open(path).await.unwrap();
+        let mut lines = vec![];
+        while let Some(line) = reader.next_line().await.unwrap() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    #[test]
+    fn compression_by_extension() {
+        assert_eq!(FileCompression::of("app.log.1.gz"), Some(FileCompression::Gzip));
+        assert_eq!(FileCompression::of("app.log.zst"), Some(FileCompression::Zstd));
+        assert_eq!(FileCompression::of("app.log"), None);
+        assert_eq!(FileCompression::of("app.gz.log"), None);
+    }
+
+    #[tokio::test]
+    async fn compressed_files_are_read_decompressed() {
+        let dir = env::temp_dir().join(format!("fefs-compressed-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let content = b"one\r\ntwo\nthree";
+        let plain = dir.join("app.log");
+        fs::write(&plain, content).unwrap();
+        let gzip = dir.join("app.log.1.gz");
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(content).unwrap();
+        fs::write(&gzip, encoder.finish().unwrap()).unwrap();
+        let zstd = dir.join("app.log.2.zst");
+        fs::write(&zstd, zstd::encode_all(&content[..], 0).unwrap()).unwrap();
+        let broken = dir.join("app.log.3.gz");
+        fs::write(&broken, content).unwrap();
+
+        let mut read = vec![];
+        for path in [&plain, &gzip, &zstd] {
+            read.push(lines(&path.display().to_string()).await);
+        }
+        let mut reader = LineReader::open(&broken.display().to_string()).await.unwrap();
+        let broken = reader.next_line().await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        for lines in read {
+            assert_eq!(lines, ["one", "two", "three"]);
+        }
+        assert!(matches!(broken, Err(ClientError::Read { .. })));
+    }
+}
@@ -1,62 +1,574 @@
+use std::{path::Path, str::FromStr, time::Duration};
+
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{backoff::BackoffConfiguration, interpolation, selection::FileSelection, validation::{self, ConfigurationIssue}};
+use crate::{message::codec::{Compression, WireEncoding, WireFormat}, Applicatiton};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ServerEndpoint {
+    host: String,
+    port: u16,
+    path: String,
+    #[serde(default)]
+    tls: bool,
+    // pem bundle used instead of the system trust store, e.g. for self-signed server certificates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ca_file: Option<String>
+}
+
+// accepts ws://host:port/path, wss://host:port/path or host:port/path, the path defaults to ws
+impl FromStr for ServerEndpoint {
+    type Err = String;
+
+    fn from_str(server: &str) -> Result<Self, Self::Err> {
+        let (tls, rest) = if let Some(rest) = server.strip_prefix("wss://") {
+            (true, rest)
+        } else {
+            (false, server.strip_prefix("ws://").unwrap_or(server))
+        };
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, "ws"));
+        let (host, port) = authority.rsplit_once(':')
+            .ok_or_else(|| format!("missing port in server {}", server))?;
+        let port = port.parse().map_err(|e| format!("invalid port in server {}: {}", server, e))?;
+        if host.is_empty() {
+            return Err(format!("missing host in server {}", server));
+        }
+        Ok(Self { host: host.to_string(), port, path: path.to_string(), tls, ca_file: None })
+    }
+}
+
+impl ServerEndpoint {
+    pub fn get_host(&self) -> String {
+        self.host.clone()
+    }
 
-use crate::Applicatiton;
+    pub fn get_port(&self) -> u16 {
+        self.port
+    }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+    pub fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self.tls
+    }
+
+    pub fn get_ca_file(&self) -> Option<String> {
+        self.ca_file.clone()
+    }
+
+    pub fn authority(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    pub fn uri(&self) -> String {
+        let scheme = if self.tls { "wss" } else { "ws" };
+        format!("{}://{}/{}", scheme, self.authority(), self.path)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Delivery {
+    // send to the first reachable server and move on to the next one when it goes down
+    #[default]
+    Failover,
+    // send every message to all servers
+    Mirror
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct LogConfiguration {
     #[serde(rename = "app_name")]
     application: Applicatiton,
-    log_file_dir: String,
+    // log_file_dir, when given, is searched before log_file_dirs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    log_file_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    log_file_dirs: Vec<String>,
     log_file_name_regex: String,
-    server_host: String,
-    server_port: i16,
-    server_path: String,
-    channel_buffer: usize
+    #[serde(default)]
+    file_selection: FileSelection,
+    // files not modified for longer are left alone, so only the active log is picked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_file_age_secs: Option<u64>,
+    // how long the current file has to be quiet before the tailer moves on to a newer one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rollover_quiet_period_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    server_host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    server_port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    server_path: Option<String>,
+    #[serde(default)]
+    server_tls: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    server_ca_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    servers: Vec<ServerEndpoint>,
+    #[serde(default)]
+    delivery: Delivery,
+    // sent to every server as a bearer token, either a pre-shared key or a jwt naming this application
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth_token: Option<String>,
+    // pem certificate and key presented to servers that require mutual tls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client_certificate: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client_key: Option<String>,
+    channel_buffer: usize,
+    // lines kept while the server has paused the application, the oldest go first once it's full
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pause_buffer_lines: Option<usize>,
+    // buffered lines older than this aren't sent on resume
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pause_buffer_max_age_secs: Option<u64>,
+    // how often the tailer's stats are sent to the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stats_interval_secs: Option<u64>,
+    #[serde(default)]
+    wire_format: WireFormat,
+    #[serde(default)]
+    compression: Compression,
+    #[serde(default)]
+    reconnect: BackoffConfiguration
 }
 
 impl LogConfiguration {
+    pub fn new(application: Applicatiton, log_file_dir: String, log_file_name_regex: String, servers: Vec<ServerEndpoint>) -> Self {
+        Self {
+            application,
+            log_file_dir: Some(log_file_dir),
+            log_file_dirs: vec![],
+            log_file_name_regex,
+            file_selection: FileSelection::default(),
+            max_file_age_secs: None,
+            rollover_quiet_period_ms: None,
+            server_host: None,
+            server_port: None,
+            server_path: None,
+            server_tls: false,
+            server_ca_file: None,
+            servers,
+            delivery: Delivery::default(),
+            auth_token: None,
+            client_certificate: None,
+            client_key: None,
+            channel_buffer: 10,
+            pause_buffer_lines: None,
+            pause_buffer_max_age_secs: None,
+            stats_interval_secs: None,
+            wire_format: WireFormat::default(),
+            compression: Compression::default(),
+            reconnect: BackoffConfiguration::default()
+        }
+    }
+
     pub fn get_application(&self) -> Applicatiton {
         self.application.clone()
     }
 
-    pub fn get_log_file_dir(&self) -> String {
-        self.log_file_dir.clone()
+    pub fn get_log_file_dirs(&self) -> Vec<String> {
+        self.log_file_dir.iter().chain(self.log_file_dirs.iter()).cloned().collect()
+    }
+
+    // the field of each directory in get_log_file_dirs, for reporting problems with them
+    pub fn get_log_file_dir_fields(&self) -> Vec<String> {
+        let mut fields: Vec<String> = (0..self.log_file_dirs.len()).map(|index| format!("log_file_dirs[{}]", index)).collect();
+        if self.log_file_dir.is_some() {
+            fields.insert(0, "log_file_dir".to_string());
+        }
+        fields
     }
 
     pub fn get_log_file_name_regex(&self) -> String {
         self.log_file_name_regex.clone()
     }
 
-    pub fn get_server_host(&self) -> String {
-        self.server_host.clone()
+    pub fn get_file_selection(&self) -> FileSelection {
+        self.file_selection
+    }
+
+    pub fn get_max_file_age(&self) -> Option<Duration> {
+        self.max_file_age_secs.map(Duration::from_secs)
+    }
+
+    pub fn get_rollover_quiet_period(&self) -> Duration {
+        self.rollover_quiet_period_ms.map(Duration::from_millis).unwrap_or(DEFAULT_ROLLOVER_QUIET_PERIOD)
+    }
+
+    // the single server_host/server_port/server_path endpoint, when given, is tried before the servers list
+    pub fn get_servers(&self) -> Vec<ServerEndpoint> {
+        let mut servers = self.servers.clone();
+        if let (Some(host), Some(port)) = (&self.server_host, self.server_port) {
+            let path = self.server_path.clone().unwrap_or_default();
+            servers.insert(0, ServerEndpoint {
+                host: host.clone(),
+                port,
+                path,
+                tls: self.server_tls,
+                ca_file: self.server_ca_file.clone()
+            });
+        }
+        servers
     }
 
-    pub fn get_server_port(&self) -> i16 {
-        self.server_port
+    // the prefix of the host and port fields of each server in get_servers, for reporting problems with them
+    pub fn get_server_field_prefixes(&self) -> Vec<String> {
+        let mut prefixes: Vec<String> = (0..self.servers.len()).map(|index| format!("servers[{}].", index)).collect();
+        if self.server_host.is_some() && self.server_port.is_some() {
+            prefixes.insert(0, "server_".to_string());
+        }
+        prefixes
     }
 
-    pub fn get_server_path(&self) -> String {
-        self.server_path.clone()
+    pub fn has_partial_legacy_server(&self) -> bool {
+        self.server_host.is_some() != self.server_port.is_some()
+    }
+
+    pub fn get_delivery(&self) -> Delivery {
+        self.delivery
+    }
+
+    pub fn get_auth_token(&self) -> Option<String> {
+        self.auth_token.clone()
+    }
+
+    pub fn has_partial_client_identity(&self) -> bool {
+        self.client_certificate.is_some() != self.client_key.is_some()
+    }
+
+    pub fn get_client_identity(&self) -> Option<(String, String)> {
+        match (&self.client_certificate, &self.client_key) {
+            (Some(certificate), Some(key)) => Some((certificate.clone(), key.clone())),
+            _ => None,
+        }
     }
 
     pub fn get_channel_buffer(&self) -> usize {
         self.channel_buffer
     }
+
+    pub fn get_pause_buffer_lines(&self) -> usize {
+        self.pause_buffer_lines.unwrap_or(DEFAULT_PAUSE_BUFFER_LINES)
+    }
+
+    pub fn get_pause_buffer_max_age(&self) -> Duration {
+        self.pause_buffer_max_age_secs.map(Duration::from_secs).unwrap_or(DEFAULT_PAUSE_BUFFER_MAX_AGE)
+    }
+
+    pub fn get_stats_interval(&self) -> Duration {
+        self.stats_interval_secs.map(Duration::from_secs).unwrap_or(DEFAULT_STATS_INTERVAL)
+    }
+
+    pub fn get_wire_encoding(&self) -> WireEncoding {
+        WireEncoding::new(self.wire_format, self.compression)
+    }
+
+    pub fn get_reconnect(&self) -> BackoffConfiguration {
+        self.reconnect.clone()
+    }
+}
+
+pub const CONFIGURATION_FILE: &str = "fefs_config.json";
+const DEFAULT_ROLLOVER_QUIET_PERIOD: Duration = Duration::from_secs(5);
+const DEFAULT_PAUSE_BUFFER_LINES: usize = 1000;
+const DEFAULT_PAUSE_BUFFER_MAX_AGE: Duration = Duration::from_secs(300);
+const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_DISCONNECTED: Duration = Duration::from_secs(60);
+pub const DEFAULT_SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum ConfigurationError {
+    #[error("failed to read configuration file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse configuration file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("failed to parse configuration file: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("failed to parse configuration file: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("failed to interpolate configuration file: {0}")]
+    Interpolation(ConfigurationIssue),
+    #[error("invalid configuration file: {0}")]
+    Invalid(#[from] serde_path_to_error::Error<serde_json::Error>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ClientConfiguration {
     #[serde(rename = "configs")]
-    configurations: Vec<LogConfiguration>
+    configurations: Vec<LogConfiguration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status_address: Option<String>,
+    // how long an application may have no connection up before /healthz fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_disconnected_secs: Option<u64>,
+    // where read positions are saved on shutdown, without one tailing restarts at the end of the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checkpoint_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shutdown_grace_period_ms: Option<u64>,
+    // problems with the log configurations that were left out
+    #[serde(skip)]
+    issues: Vec<ConfigurationIssue>
+}
+
+impl Default for ClientConfiguration {
+    fn default() -> Self {
+        let server = ServerEndpoint::from_str("localhost:8080/ws").unwrap();
+        let config = LogConfiguration::new(
+            Applicatiton::SinglePod("my-application".to_string()),
+            ".".to_string(),
+            r"^log.*\.txt$".to_string(),
+            vec![server]
+        );
+        Self::new(vec![config])
+    }
 }
 
 impl ClientConfiguration {
-    pub fn read_from_file() -> Self {
-        let config = std::fs::read_to_string("fefs_config.json").unwrap();
-        serde_json::from_str(&config).unwrap()
+    pub fn new(configurations: Vec<LogConfiguration>) -> Self {
+        Self { configurations, status_address: None, max_disconnected_secs: None, checkpoint_dir: None, shutdown_grace_period_ms: None, issues: vec![] }
+    }
+
+    // the format follows the file extension, .toml, .yaml or .yml and json for anything else
+    pub fn load(path: &str) -> Result<Self, ConfigurationError> {
+        let config = std::fs::read_to_string(path)?;
+        let extension = Path::new(path).extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+        let value = match extension.to_lowercase().as_str() {
+            "toml" => toml::from_str(&config)?,
+            "yaml" | "yml" => serde_yaml::from_str(&config)?,
+            _ => serde_json::from_str(&config)?,
+        };
+        Self::from_value(value)
+    }
+
+    // log configurations are parsed and validated one by one, so a broken one is reported and skipped
+    // instead of taking the others down with it
+    fn from_value(mut value: serde_json::Value) -> Result<Self, ConfigurationError> {
+        let entries = match value.get_mut("configs") {
+            Some(serde_json::Value::Array(entries)) => std::mem::take(entries),
+            _ => vec![],
+        };
+        if let Some(issue) = interpolation::interpolate(&mut value, "").into_iter().next() {
+            return Err(ConfigurationError::Interpolation(issue));
+        }
+        let mut client_configuration: Self = serde_path_to_error::deserialize(value)?;
+
+        let mut configurations = vec![];
+        let mut issues = vec![];
+        for (index, mut entry) in entries.into_iter().enumerate() {
+            let path = format!("configs[{}]", index);
+            let interpolation_issues = interpolation::interpolate(&mut entry, &path);
+            let application = entry.get("app_name")
+                .and_then(|application| serde_json::from_value::<Applicatiton>(application.clone()).ok());
+            if !interpolation_issues.is_empty() {
+                issues.extend(interpolation_issues.into_iter().map(|issue| issue.for_application(application.clone())));
+                continue;
+            }
+            match serde_path_to_error::deserialize::<_, LogConfiguration>(entry) {
+                Ok(configuration) => configurations.push((path, configuration)),
+                Err(e) => {
+                    let field = e.path().to_string();
+                    let path = if field == "." { path } else { format!("{}.{}", path, field) };
+                    issues.push(ConfigurationIssue::new(path, e.into_inner().to_string()).for_application(application));
+                }
+            }
+        }
+
+        let (configurations, validation_issues) = validation::validate_all(configurations);
+        issues.extend(validation_issues);
+        client_configuration.configurations = configurations;
+        client_configuration.issues = issues;
+        Ok(client_configuration)
+    }
+
+    pub fn get_issues(&self) -> &[ConfigurationIssue] {
+        &self.issues
+    }
+
+    pub fn get_status_address(&self) -> Option<String> {
+        self.status_address.clone()
+    }
+
+    pub fn get_max_disconnected(&self) -> Duration {
+        self.max_disconnected_secs.map(Duration::from_secs).unwrap_or(DEFAULT_MAX_DISCONNECTED)
+    }
+
+    pub fn get_checkpoint_dir(&self) -> Option<String> {
+        self.checkpoint_dir.clone()
+    }
+
+    // how long stopping tailers get to flush what they read before the client exits anyway
+    pub fn get_shutdown_grace_period(&self) -> Duration {
+        self.shutdown_grace_period_ms.map(Duration::from_millis).unwrap_or(DEFAULT_SHUTDOWN_GRACE_PERIOD)
     }
 
     pub fn get_configurations(self) -> Vec<LogConfiguration> {
         self.configurations
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn legacy_server_goes_first() {
+        let config: LogConfiguration = serde_json::from_value(json!({
+            "app_name": { "SinglePod": "app" },
+            "log_file_dir": ".",
+            "log_file_name_regex": ".*",
+            "server_host": "primary",
+            "server_port": 8080,
+            "server_path": "ws",
+            "servers": [{ "host": "secondary", "port": 8081, "path": "ingest" }],
+            "channel_buffer": 10
+        })).unwrap();
+
+        let uris: Vec<String> = config.get_servers().iter().map(ServerEndpoint::uri).collect();
+        assert_eq!(uris, vec!["ws://primary:8080/ws", "ws://secondary:8081/ingest"]);
+        assert_eq!(config.get_delivery(), Delivery::Failover);
+    }
+
+    #[test]
+    fn servers_only() {
+        let config: LogConfiguration = serde_json::from_value(json!({
+            "app_name": { "SinglePod": "app" },
+            "log_file_dir": ".",
+            "log_file_name_regex": ".*",
+            "servers": [{ "host": "a", "port": 1, "path": "ws" }, { "host": "b", "port": 2, "path": "ws" }],
+            "delivery": "Mirror",
+            "channel_buffer": 10
+        })).unwrap();
+
+        let authorities: Vec<String> = config.get_servers().iter().map(ServerEndpoint::authority).collect();
+        assert_eq!(authorities, vec!["a:1", "b:2"]);
+        assert_eq!(config.get_delivery(), Delivery::Mirror);
+    }
+
+    #[test]
+    fn server_from_str() {
+        let server = ServerEndpoint::from_str("wss://example.com:8443/ingest").unwrap();
+        assert_eq!(server.uri(), "wss://example.com:8443/ingest");
+        assert!(server.is_tls());
+
+        assert_eq!(ServerEndpoint::from_str("ws://example.com:8080/ws").unwrap().uri(), "ws://example.com:8080/ws");
+        assert_eq!(ServerEndpoint::from_str("example.com:8080").unwrap().uri(), "ws://example.com:8080/ws");
+        assert_eq!(ServerEndpoint::from_str("[::1]:8080/ws").unwrap().authority(), "[::1]:8080");
+    }
+
+    #[test]
+    fn invalid_server_from_str() {
+        assert!(ServerEndpoint::from_str("example.com").is_err());
+        assert!(ServerEndpoint::from_str("example.com:http/ws").is_err());
+        assert!(ServerEndpoint::from_str(":8080/ws").is_err());
+    }
+
+    #[test]
+    fn default_configuration_round_trips() {
+        let json = serde_json::to_string(&ClientConfiguration::default()).unwrap();
+        assert!(!json.contains("null"));
+        let configuration: ClientConfiguration = serde_json::from_str(&json).unwrap();
+        assert_eq!(configuration.get_configurations(), ClientConfiguration::default().get_configurations());
+    }
+
+    #[test]
+    fn broken_configurations_are_skipped() {
+        let configuration = ClientConfiguration::from_value(json!({
+            "configs": [
+                { "app_name": { "SinglePod": "app" }, "log_file_dir": ".", "log_file_name_regex": ".*", "server_host": "localhost", "server_port": 8080, "channel_buffer": 10 },
+                { "app_name": { "SinglePod": "broken" }, "log_file_dir": ".", "log_file_name_regex": ".*", "server_host": "localhost", "server_port": 8080, "channel_buffer": "ten" },
+                { "app_name": { "SinglePod": "invalid" }, "log_file_dir": ".", "log_file_name_regex": ".*", "server_host": "localhost", "server_port": 8080, "channel_buffer": 0 }
+            ]
+        })).unwrap();
+
+        let paths: Vec<&str> = configuration.get_issues().iter().map(ConfigurationIssue::path).collect();
+        assert_eq!(paths, vec!["configs[1].channel_buffer", "configs[2].channel_buffer"]);
+        let applications: Vec<Applicatiton> = configuration.get_configurations().iter().map(LogConfiguration::get_application).collect();
+        assert_eq!(applications, vec![Applicatiton::SinglePod("app".to_string())]);
+    }
+
+    #[test]
+    fn broken_top_level_is_an_error() {
+        assert!(ClientConfiguration::from_value(json!({ "configs": [], "status_address": 8080 })).is_err());
+    }
+
+    fn load(name: &str, contents: &str) -> Result<ClientConfiguration, ConfigurationError> {
+        let path = std::env::temp_dir().join(format!("fefs-configuration-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        let configuration = ClientConfiguration::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        configuration
+    }
+
+    #[test]
+    fn toml_and_yaml() {
+        let toml = r#"
+            status_address = "127.0.0.1:9000"
+
+            [[configs]]
+            log_file_dir = "."
+            log_file_name_regex = ".*"
+            server_host = "localhost"
+            server_port = 8080
+            channel_buffer = 10
+            app_name = { SinglePod = "app" }
+        "#;
+        let yaml = r#"
+            status_address: 127.0.0.1:9000
+            configs:
+              - app_name:
+                  SinglePod: app
+                log_file_dir: .
+                log_file_name_regex: .*
+                server_host: localhost
+                server_port: 8080
+                channel_buffer: 10
+        "#;
+
+        for (name, contents) in [("config.toml", toml), ("config.yaml", yaml), ("config.YML", yaml)] {
+            let configuration = load(name, contents).unwrap();
+            assert!(configuration.get_issues().is_empty(), "{}", name);
+            assert_eq!(configuration.get_status_address().as_deref(), Some("127.0.0.1:9000"));
+            assert_eq!(configuration.get_configurations()[0].get_application(), Applicatiton::SinglePod("app".to_string()));
+        }
+        assert!(matches!(load("config.json", toml), Err(ConfigurationError::Json(_))));
+    }
+
+    #[test]
+    fn interpolation_issues() {
+        std::env::remove_var("FEFS_CONFIGURATION_UNSET");
+        let configuration = ClientConfiguration::from_value(json!({
+            "configs": [
+                { "app_name": { "SinglePod": "app" }, "log_file_dir": "${FEFS_CONFIGURATION_UNSET}", "log_file_name_regex": ".*", "server_host": "localhost", "server_port": 8080, "channel_buffer": 10 }
+            ]
+        })).unwrap();
+        let paths: Vec<&str> = configuration.get_issues().iter().map(ConfigurationIssue::path).collect();
+        assert_eq!(paths, vec!["configs[0].log_file_dir"]);
+
+        let error = ClientConfiguration::from_value(json!({ "configs": [], "status_address": "${FEFS_CONFIGURATION_UNSET}" }));
+        assert!(matches!(error, Err(ConfigurationError::Interpolation(issue)) if issue.path() == "status_address"));
+    }
+
+    #[test]
+    fn issues_name_their_application() {
+        let value = serde_json::json!({
+            "configs": [
+                { "app_name": { "SinglePod": "bad regex" }, "log_file_dir": std::env::temp_dir(), "log_file_name_regex": "(", "server_host": "localhost", "server_port": 8080, "channel_buffer": 10 },
+                { "app_name": { "SinglePod": "no buffer" }, "log_file_dir": std::env::temp_dir(), "log_file_name_regex": ".*", "server_host": "localhost", "server_port": 8080 },
+                { "app_name": "not an application" }
+            ]
+        });
+
+        let configuration = ClientConfiguration::from_value(value).unwrap();
+        let applications: Vec<_> = configuration.get_issues().iter().map(|issue| issue.application().map(Applicatiton::name)).collect();
+        assert_eq!(applications, vec![Some("no buffer".to_string()), None, Some("bad regex".to_string())]);
+        assert!(configuration.get_configurations().is_empty());
+    }
+}
@@ -0,0 +1,490 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use tokio::{net::TcpStream, sync::{mpsc, watch, Mutex}, time};
+use tokio_tungstenite::{connect_async_tls_with_config, Connector, MaybeTlsStream, WebSocketStream};
+use tungstenite::{handshake::client::generate_key, http::Request, protocol::{frame::coding::CloseCode, CloseFrame}, Error, Message};
+
+use crate::{message::{self, codec::{WireEncoding, WireFrame, WIRE_ENCODING_HEADER}, SystemMessage, SystemMessages}, tls, Applicatiton};
+
+use super::{configuration::{LogConfiguration, ServerEndpoint}, error::ClientError, shutdown::Shutdown};
+
+// how often a connection looks for applications that left it, it closes once none are left
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const CONTROL_BUFFER: usize = 16;
+const OUTBOUND_BUFFER: usize = 1024;
+
+// applications share a connection when they go to the same server with the same settings:
+// uri, ca file, auth token, client identity and wire encoding offer
+type ConnectionKey = (String, Option<String>, Option<String>, Option<(String, String)>, String);
+
+fn connection_key(config: &LogConfiguration, server: &ServerEndpoint) -> ConnectionKey {
+    (server.uri(), server.get_ca_file(), config.get_auth_token(), config.get_client_identity(), config.get_wire_encoding().offer())
+}
+
+// the connection to one server with one set of settings, if it's been opened
+type Slot = Arc<Mutex<Option<Arc<Shared>>>>;
+
+// one websocket per server and settings, whichever applications are sent over it
+pub struct Connections {
+    slots: Mutex<BTreeMap<ConnectionKey, Slot>>
+}
+
+struct Shared {
+    // where the server's messages for each application go
+    routes: std::sync::Mutex<BTreeMap<Applicatiton, mpsc::Sender<message::Message>>>,
+    outbound: mpsc::Sender<message::Message>,
+    // never sent to, it's dropped when the connection is gone
+    closed: watch::Receiver<()>
+}
+
+impl Shared {
+    fn is_open(&self) -> bool {
+        !self.outbound.is_closed()
+    }
+
+    // forgets the applications whose link was dropped and returns how many are left
+    fn remove_detached(&self) -> usize {
+        let mut routes = self.routes.lock().unwrap();
+        routes.retain(|_, control| !control.is_closed());
+        routes.len()
+    }
+}
+
+// one application's share of a connection
+pub struct Link {
+    application: Applicatiton,
+    control: mpsc::Receiver<message::Message>,
+    outbound: mpsc::Sender<message::Message>,
+    // the server started the application and hasn't been told it stopped
+    registered: bool
+}
+
+impl Link {
+    // messages from the server for this application, None once the connection is gone
+    pub async fn recv(&mut self) -> Option<message::Message> {
+        self.control.recv().await
+    }
+
+    pub async fn send(&self, msg: message::Message) -> Result<(), ClientError> {
+        self.outbound.send(msg).await.map_err(|_| ClientError::ChannelClosed)
+    }
+
+    // for sending on the application's behalf from elsewhere
+    pub fn sender(&self) -> mpsc::Sender<message::Message> {
+        self.outbound.clone()
+    }
+
+    pub fn started(&mut self) {
+        self.registered = true;
+    }
+
+    // tells the server the application is done, so it unregisters it
+    pub async fn stop(&mut self) {
+        self.registered = false;
+        let _ = self.send(system_message(&self.application, SystemMessages::Stop)).await;
+    }
+}
+
+impl Drop for Link {
+    // an application stopped without stop(), e.g. aborted on a configuration reload
+    fn drop(&mut self) {
+        if self.registered {
+            let _ = self.outbound.try_send(system_message(&self.application, SystemMessages::Stop));
+        }
+    }
+}
+
+impl Default for Connections {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Connections {
+    pub fn new() -> Self {
+        Self { slots: Mutex::new(BTreeMap::new()) }
+    }
+
+    // joins the open connection to the server or opens one, then registers the application on it,
+    // the server answers with Start or with an Error if the application isn't allowed
+    pub async fn attach(&self, config: &LogConfiguration, server: &ServerEndpoint, shutdown: Shutdown) -> Result<Link, ClientError> {
+        let slot = {
+            let mut slots = self.slots.lock().await;
+            Arc::clone(slots.entry(connection_key(config, server)).or_default())
+        };
+        // held while connecting, so applications starting together don't open a connection each
+        let mut slot = slot.lock().await;
+        let shared = match slot.as_ref() {
+            Some(shared) if shared.is_open() => Arc::clone(shared),
+            _ => {
+                let shared = open(config, server, shutdown).await?;
+                *slot = Some(Arc::clone(&shared));
+                shared
+            }
+        };
+        drop(slot);
+
+        let application = config.get_application();
+        let (control_tx, control) = mpsc::channel(CONTROL_BUFFER);
+        shared.routes.lock().unwrap().insert(application.clone(), control_tx);
+        let link = Link {
+            application: application.clone(),
+            control,
+            outbound: shared.outbound.clone(),
+            registered: false
+        };
+        link.send(system_message(&application, SystemMessages::Register)).await?;
+        Ok(link)
+    }
+
+    // waits for every connection to send what it was given and close
+    pub async fn closed(&self) {
+        let slots: Vec<_> = self.slots.lock().await.values().cloned().collect();
+        for slot in slots {
+            let closed = slot.lock().await.as_ref().map(|shared| shared.closed.clone());
+            if let Some(mut closed) = closed {
+                let _ = closed.changed().await;
+            }
+        }
+    }
+}
+
+async fn open(config: &LogConfiguration, server: &ServerEndpoint, shutdown: Shutdown) -> Result<Arc<Shared>, ClientError> {
+    let (ws_stream, encoding) = connect(config, server).await?;
+    info!("webSocket connected to {} using {} wire encoding", server.uri(), encoding.header_value());
+
+    let (outbound, outbound_rx) = mpsc::channel(OUTBOUND_BUFFER);
+    let (closed_tx, closed) = watch::channel(());
+    let shared = Arc::new(Shared { routes: std::sync::Mutex::new(BTreeMap::new()), outbound, closed });
+    tokio::spawn(run(Arc::clone(&shared), ws_stream, encoding, outbound_rx, shutdown, closed_tx));
+    Ok(shared)
+}
+
+async fn connect(config: &LogConfiguration, server: &ServerEndpoint) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, WireEncoding), ClientError> {
+    let host = server.authority();
+    let uri = server.uri();
+
+    info!("connecting to {}", uri);
+    // without an Application header the server expects a Register message for each application
+    let mut request = Request::builder()
+        .uri(&uri)
+        .header("Host", host)
+        .header("Sec-WebSocket-Key", generate_key())
+        .header("Sec-WebSocket-Version", "13")
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade")
+        .header(WIRE_ENCODING_HEADER, config.get_wire_encoding().offer());
+    if let Some(token) = config.get_auth_token() {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let request = request.body(())?;
+
+    let connector = if server.is_tls() {
+        let client_identity = config.get_client_identity();
+        let client_identity = client_identity.as_ref().map(|(certificate, key)| (certificate.as_str(), key.as_str()));
+        let tls_config = tls::client_config(server.get_ca_file().as_deref(), client_identity).map_err(ClientError::Tls)?;
+        Some(Connector::Rustls(Arc::new(tls_config)))
+    } else {
+        None
+    };
+
+    let (ws_stream, response) = connect_async_tls_with_config(request, None, false, connector).await?;
+
+    // servers that predate wire encoding negotiation don't answer the header and only speak json
+    let encoding = response.headers().get(WIRE_ENCODING_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(WireEncoding::parse)
+        .unwrap_or_default();
+
+    Ok((ws_stream, encoding))
+}
+
+async fn run(
+    shared: Arc<Shared>,
+    ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    encoding: WireEncoding,
+    mut outbound: mpsc::Receiver<message::Message>,
+    shutdown: Shutdown,
+    _closed: watch::Sender<()>
+) {
+    let (mut write, mut read) = ws_stream.split();
+    let mut idle_check = time::interval_at(time::Instant::now() + IDLE_CHECK_INTERVAL, IDLE_CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            msg = outbound.recv() => {
+                let msg = match msg {
+                    Some(msg) => msg,
+                    None => break,
+                };
+                if let Some(frame) = to_frame(&encoding, &msg) {
+                    if let Err(e) = write.send(frame).await {
+                        error!("Error sending message: {}", e);
+                        break;
+                    }
+                }
+            },
+            message = read.next() => {
+                match message {
+                    Some(message) => if !process_message(message, &shared) {
+                        break;
+                    },
+                    None => break,
+                }
+            },
+            _ = idle_check.tick() => {
+                if shared.remove_detached() > 0 {
+                    continue;
+                }
+                // the last applications may have left their Stop behind
+                while let Ok(msg) = outbound.try_recv() {
+                    if let Some(frame) = to_frame(&encoding, &msg) {
+                        let _ = write.send(frame).await;
+                    }
+                }
+                let reason = if shutdown.is_requested() { "client shutting down" } else { "no applications left" };
+                close(&mut write, reason).await;
+                break;
+            }
+        }
+    }
+
+    // closing every route ends the applications' sessions, so they reconnect or fail over on their own
+    outbound.close();
+    shared.routes.lock().unwrap().clear();
+    info!("client stopped");
+}
+
+fn system_message(application: &Applicatiton, message: SystemMessages) -> message::Message {
+    message::Message::System(SystemMessage::new(application.clone(), message))
+}
+
+fn to_frame(encoding: &WireEncoding, msg: &message::Message) -> Option<Message> {
+    match encoding.encode(msg) {
+        Ok(WireFrame::Text(text)) => Some(Message::Text(text)),
+        Ok(WireFrame::Binary(bytes)) => Some(Message::Binary(bytes)),
+        Err(e) => {
+            error!("Error encoding message: {}", e);
+            None
+        }
+    }
+}
+
+async fn close<S: SinkExt<Message> + Unpin>(write: &mut S, reason: &'static str) {
+    let frame = CloseFrame { code: CloseCode::Away, reason: reason.into() };
+    if write.send(Message::Close(Some(frame))).await.is_err() {
+        debug!("connection was gone before the close frame");
+    }
+    info!("closed connection, {}", reason);
+}
+
+// never waits, an application that doesn't take its messages must not hold up the others on the connection.
+// it's detached instead of losing the message, once it has handled the rest its link ends and it registers
+// again, so the server starts it over in the state it's in now
+fn route(shared: &Shared, application: &Applicatiton, control: &mpsc::Sender<message::Message>, message: message::Message) {
+    match control.try_send(message) {
+        Ok(()) => {}
+        Err(mpsc::error::TrySendError::Full(message)) => {
+            error!("Detaching {}, it left {} server messages unhandled and can't take {:?}", application.name(), control.max_capacity(), message);
+            let mut routes = shared.routes.lock().unwrap();
+            if routes.get(application).is_some_and(|route| route.same_channel(control)) {
+                routes.remove(application);
+            }
+        }
+        // the application left, the idle check forgets it
+        Err(mpsc::error::TrySendError::Closed(_)) => {}
+    }
+}
+
+// a message the client can't make sense of is skipped and reported back to the server
+fn process_message(message: Result<Message, Error>, shared: &Shared) -> bool {
+    match message {
+        Ok(msg) => {
+            match msg {
+                Message::Text(text) => {
+                    let message = match serde_json::from_str::<message::Message>(&text) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            let e = ClientError::from(e);
+                            error!("Error processing server message: {}", e);
+                            let routes: Vec<_> = shared.routes.lock().unwrap().iter()
+                                .map(|(application, control)| (application.clone(), control.clone()))
+                                .collect();
+                            for (application, control) in routes {
+                                route(shared, &application, &control, system_message(&application, SystemMessages::Error(e.to_string())));
+                            }
+                            return true
+                        }
+                    };
+                    let control = message.application()
+                        .and_then(|application| shared.routes.lock().unwrap().get(application).map(|control| (application.clone(), control.clone())));
+                    match control {
+                        Some((application, control)) => route(shared, &application, &control, message),
+                        None => warn!("Dropping server message for an application that isn't attached"),
+                    }
+                },
+                Message::Binary(data) => info!("Received binary data: {:?}", data),
+                Message::Ping(_) => debug!("Received ping"),
+                Message::Pong(_) => debug!("Received pong"),
+                Message::Close(frame) => {
+                    match frame {
+                        Some(frame) => info!("Server closed connection with code {} and reason: {}", frame.code, frame.reason),
+                        None => info!("Server closed connection"),
+                    }
+                    return false
+                },
+                Message::Frame(_) => warn!("Received raw frame"),
+            }
+        }
+        Err(e) => {
+            error!("Error receiving message: {}", e);
+            return false
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::str::FromStr;
+
+    use tokio::net::TcpListener;
+
+    use crate::message::DataMessage;
+
+    use super::*;
+
+    // accepts a single connection, hands what it receives to the test, None once it's closed, and sends what it's given
+    pub(in crate::client) async fn server() -> (ServerEndpoint, mpsc::UnboundedReceiver<Option<message::Message>>, mpsc::UnboundedSender<message::Message>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = ServerEndpoint::from_str(&format!("127.0.0.1:{}", listener.local_addr().unwrap().port())).unwrap();
+        let (received_tx, received) = mpsc::unbounded_channel();
+        let (send, mut to_send) = mpsc::unbounded_channel::<message::Message>();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            // a second connection is refused
+            drop(listener);
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            loop {
+                tokio::select! {
+                    msg = to_send.recv() => match msg {
+                        Some(msg) => ws.send(Message::Text(serde_json::to_string(&msg).unwrap())).await.unwrap(),
+                        None => break,
+                    },
+                    frame = ws.next() => match frame {
+                        Some(Ok(Message::Text(text))) => {
+                            let _ = received_tx.send(Some(serde_json::from_str(&text).unwrap()));
+                        },
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                            let _ = received_tx.send(None);
+                            break;
+                        },
+                        Some(Ok(_)) => {}
+                    }
+                }
+            }
+        });
+        (server, received, send)
+    }
+
+    fn config(name: &str, server: &ServerEndpoint) -> LogConfiguration {
+        LogConfiguration::new(application(name), ".".to_string(), ".*".to_string(), vec![server.clone()])
+    }
+
+    fn application(name: &str) -> Applicatiton {
+        Applicatiton::SinglePod(name.to_string())
+    }
+
+    async fn next<T>(rx: &mut mpsc::UnboundedReceiver<T>) -> T {
+        time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap()
+    }
+
+    fn system(message: Option<message::Message>) -> (Applicatiton, SystemMessages) {
+        let message = message.unwrap();
+        let message = message.system().unwrap();
+        (message.application().clone(), message.message().clone())
+    }
+
+    async fn shared(connections: &Connections) -> Arc<Shared> {
+        let slot = connections.slots.lock().await.values().next().cloned().unwrap();
+        let shared = slot.lock().await.clone().unwrap();
+        shared
+    }
+
+    #[tokio::test]
+    async fn applications_share_one_connection() {
+        let (server, mut received, send) = server().await;
+        let (_tx, shutdown) = Shutdown::new();
+        let connections = Connections::new();
+
+        let mut first = connections.attach(&config("first", &server), &server, shutdown.clone()).await.unwrap();
+        let mut second = connections.attach(&config("second", &server), &server, shutdown.clone()).await.unwrap();
+        assert_eq!(system(next(&mut received).await), (application("first"), SystemMessages::Register));
+        assert_eq!(system(next(&mut received).await), (application("second"), SystemMessages::Register));
+
+        // each application gets its own messages
+        send.send(system_message(&application("second"), SystemMessages::Start)).unwrap();
+        send.send(system_message(&application("first"), SystemMessages::Pause)).unwrap();
+        let msg = time::timeout(Duration::from_secs(5), second.recv()).await.unwrap();
+        assert_eq!(system(msg), (application("second"), SystemMessages::Start));
+        let msg = time::timeout(Duration::from_secs(5), first.recv()).await.unwrap();
+        assert_eq!(system(msg), (application("first"), SystemMessages::Pause));
+
+        let row = DataMessage::new("a row".to_string(), application("first"), false);
+        first.send(message::Message::Data(row)).await.unwrap();
+        let msg = next(&mut received).await.unwrap();
+        assert_eq!(msg.data().unwrap().row(), "a row");
+        assert_eq!(msg.application(), Some(&application("first")));
+    }
+
+    #[tokio::test]
+    async fn stopped_applications_are_detached() {
+        let (server, mut received, send) = server().await;
+        let (_tx, shutdown) = Shutdown::new();
+        let connections = Connections::new();
+
+        let mut first = connections.attach(&config("first", &server), &server, shutdown.clone()).await.unwrap();
+        let mut second = connections.attach(&config("second", &server), &server, shutdown.clone()).await.unwrap();
+        next(&mut received).await;
+        next(&mut received).await;
+        first.started();
+        second.started();
+
+        first.stop().await;
+        drop(first);
+        assert_eq!(system(next(&mut received).await), (application("first"), SystemMessages::Stop));
+        assert_eq!(shared(&connections).await.remove_detached(), 1);
+
+        // messages for the stopped application go nowhere, the other one is still served
+        send.send(system_message(&application("first"), SystemMessages::Resume)).unwrap();
+        send.send(system_message(&application("second"), SystemMessages::Resume)).unwrap();
+        let msg = time::timeout(Duration::from_secs(5), second.recv()).await.unwrap();
+        assert_eq!(system(msg), (application("second"), SystemMessages::Resume));
+
+        // dropped without stop(), and the last one, so the connection closes
+        drop(second);
+        assert_eq!(system(next(&mut received).await), (application("second"), SystemMessages::Stop));
+        assert!(next(&mut received).await.is_none());
+        time::timeout(Duration::from_secs(5), connections.closed()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn applications_that_fall_behind_are_detached() {
+        let (outbound, _outbound_rx) = mpsc::channel(1);
+        let (_closed_tx, closed) = watch::channel(());
+        let shared = Shared { routes: std::sync::Mutex::new(BTreeMap::new()), outbound, closed };
+        let (control, mut control_rx) = mpsc::channel(1);
+        shared.routes.lock().unwrap().insert(application("slow"), control.clone());
+
+        route(&shared, &application("slow"), &control, system_message(&application("slow"), SystemMessages::Start));
+        route(&shared, &application("slow"), &control, system_message(&application("slow"), SystemMessages::Pause));
+        assert!(shared.routes.lock().unwrap().is_empty());
+        drop(control);
+
+        // what it was given is still handled, then its link ends
+        assert_eq!(system(control_rx.recv().await), (application("slow"), SystemMessages::Start));
+        assert!(control_rx.recv().await.is_none());
+    }
+}
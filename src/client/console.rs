@@ -0,0 +1,87 @@
+use std::{io::{self, Write}, str::FromStr};
+
+use log::error;
+use tokio::sync::mpsc;
+
+use crate::{message::Message, Applicatiton};
+
+// how a dry run prints what would have been sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConsoleFormat {
+    // each message as it would go over a json connection
+    #[default]
+    Json,
+    Human
+}
+
+impl FromStr for ConsoleFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "json" => Ok(ConsoleFormat::Json),
+            "human" => Ok(ConsoleFormat::Human),
+            _ => Err(format!("unknown output format {}, use json or human", format)),
+        }
+    }
+}
+
+// prints every message of the tailer to stdout until it stops, or until stdout is closed
+pub async fn print(mut rx: mpsc::Receiver<Message>, format: ConsoleFormat) {
+    while let Some(msg) = rx.recv().await {
+        let line = match format {
+            ConsoleFormat::Json => serde_json::to_string(&msg).unwrap(),
+            ConsoleFormat::Human => human(&msg),
+        };
+        if let Err(e) = writeln!(io::stdout().lock(), "{}", line) {
+            error!("Cannot print messages: {}", e);
+            return;
+        }
+    }
+}
+
+// a line that replaces the one before, after the rest of it was read, is marked with a +
+fn human(msg: &Message) -> String {
+    match msg {
+        Message::Data(data) => {
+            let marker = if data.replaces_last_row() { "+ " } else { "" };
+            format!("{} {} {}{}", data.timestamp().format("%Y-%m-%dT%H:%M:%S%.3f"), name(data.application()), marker, data.row())
+        }
+        Message::System(system) => {
+            format!("{} {} [{:?}]", system.timestamp().format("%Y-%m-%dT%H:%M:%S%.3f"), name(system.application()), system.message())
+        }
+        msg => format!("{:?}", msg),
+    }
+}
+
+fn name(application: &Applicatiton) -> String {
+    match application.pod_name() {
+        Some(pod_name) => format!("{}/{}", application.name(), pod_name),
+        None => application.name(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{message::{DataMessage, SystemMessage, SystemMessages}, MultiPodApplication};
+
+    use super::*;
+
+    #[test]
+    fn formats_by_name() {
+        assert_eq!(ConsoleFormat::from_str("json"), Ok(ConsoleFormat::Json));
+        assert_eq!(ConsoleFormat::from_str("human"), Ok(ConsoleFormat::Human));
+        assert!(ConsoleFormat::from_str("yaml").is_err());
+    }
+
+    #[test]
+    fn human_lines_name_the_application_and_pod() {
+        let pod = Applicatiton::MultiPod(MultiPodApplication::new("app".to_string(), "pod-0".to_string()));
+        let line = human(&Message::Data(DataMessage::new("a line".to_string(), Applicatiton::SinglePod("app".to_string()), false)));
+        assert!(line.ends_with(" app a line"), "{}", line);
+        let line = human(&Message::Data(DataMessage::new("the rest".to_string(), pod.clone(), true)));
+        assert!(line.ends_with(" app/pod-0 + the rest"), "{}", line);
+        let line = human(&Message::System(SystemMessage::new(pod, SystemMessages::TailingStarted)));
+        assert!(line.ends_with(" app/pod-0 [TailingStarted]"), "{}", line);
+    }
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+use super::{connection::Connections, console::ConsoleFormat, shutdown::Shutdown, status::Statuses, telemetry::Tailers};
+
+// what every tailer shares with the rest of the client
+#[derive(Clone)]
+pub struct ClientContext {
+    statuses: Arc<Statuses>,
+    tailers: Arc<Tailers>,
+    shutdown: Shutdown,
+    checkpoint_dir: Option<String>,
+    connections: Arc<Connections>,
+    // a dry run prints what the tailers read instead of connecting
+    console: Option<ConsoleFormat>
+}
+
+impl ClientContext {
+    pub fn new(statuses: Arc<Statuses>, tailers: Arc<Tailers>, shutdown: Shutdown, checkpoint_dir: Option<String>) -> Self {
+        Self { statuses, tailers, shutdown, checkpoint_dir, connections: Arc::new(Connections::new()), console: None }
+    }
+
+    pub fn with_console(self, format: ConsoleFormat) -> Self {
+        Self { console: Some(format), ..self }
+    }
+
+    // the context for one tailer, the returned sender stops it the way a shutdown would
+    pub fn with_stop(&self) -> (watch::Sender<bool>, Self) {
+        let (stop, shutdown) = self.shutdown.with_stop();
+        (stop, Self { shutdown, ..self.clone() })
+    }
+
+    pub fn get_statuses(&self) -> &Arc<Statuses> {
+        &self.statuses
+    }
+
+    pub fn get_tailers(&self) -> &Arc<Tailers> {
+        &self.tailers
+    }
+
+    pub fn get_shutdown(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_requested()
+    }
+
+    pub fn get_connections(&self) -> &Arc<Connections> {
+        &self.connections
+    }
+
+    pub fn get_console(&self) -> Option<ConsoleFormat> {
+        self.console
+    }
+
+    pub fn get_checkpoint_dir(&self) -> Option<&str> {
+        self.checkpoint_dir.as_deref()
+    }
+}
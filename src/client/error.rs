@@ -0,0 +1,56 @@
+use std::io;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("cannot read directory {path}: {source}")]
+    ReadDir { path: String, source: io::Error },
+    #[error("cannot open {path}: {source}")]
+    Open { path: String, source: io::Error },
+    #[error("cannot read metadata of {path}: {source}")]
+    Metadata { path: String, source: io::Error },
+    #[error("cannot read {path}: {source}")]
+    Read { path: String, source: io::Error },
+    #[error("cannot seek in {path}: {source}")]
+    Seek { path: String, source: io::Error },
+    #[error("file name {0} is not valid unicode")]
+    FileName(String),
+    #[error("invalid log file name regex: {0}")]
+    Regex(#[from] regex::Error),
+    #[error("message channel closed")]
+    ChannelClosed,
+    #[error("cannot build request: {0}")]
+    Request(#[from] tungstenite::http::Error),
+    #[error("cannot load tls configuration: {0}")]
+    Tls(io::Error),
+    // boxed, it's much larger than the other errors
+    #[error("cannot connect: {0}")]
+    Connect(Box<tungstenite::Error>),
+    #[error("cannot parse server message: {0}")]
+    ServerMessage(#[from] serde_json::Error),
+    #[error("{0} was replaced")]
+    Replaced(String),
+    #[error("no file is being tailed")]
+    NotTailing,
+    #[error("{0} is not a file the client tails")]
+    NotListed(String),
+    #[error("{0} is compressed, it can be read but not tailed")]
+    Compressed(String),
+    #[error("invalid search pattern: {0}")]
+    SearchPattern(regex::Error),
+}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(e: tungstenite::Error) -> Self {
+        ClientError::Connect(Box::new(e))
+    }
+}
+
+impl ClientError {
+    // files come and go and servers restart, retrying is pointless only when the regex is wrong
+    // or whoever reads the messages is gone
+    pub fn is_recoverable(&self) -> bool {
+        !matches!(self, ClientError::Regex(_) | ClientError::ChannelClosed)
+    }
+}
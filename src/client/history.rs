@@ -0,0 +1,298 @@
+use std::{cmp::Reverse, collections::VecDeque, io::SeekFrom};
+
+use chrono::NaiveDateTime;
+use log::{error, info};
+use tokio::{fs::File, io::{AsyncReadExt, AsyncSeekExt}};
+
+use crate::message::Replies;
+
+use super::{checkpoint::Checkpoint, compressed::LineReader, error::ClientError, identity::FileIdentity, requests::Replier, FileSearch};
+
+// a backfill never sends more lines than this, whatever was asked for
+pub const MAX_BACKFILL_LINES: usize = 10_000;
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+// answers a Backfill request with the lines before the tailer's position, oldest first, then Done
+pub(super) async fn backfill(search: FileSearch, position: Option<Checkpoint>, lines: Option<usize>, since: Option<NaiveDateTime>, replier: Replier) {
+    let found = match position {
+        Some(position) => read_history(&search, &position, lines, since).await,
+        None => Err(ClientError::NotTailing),
+    };
+    let found = match found {
+        Ok(found) => found,
+        Err(e) => return replier.fail(e).await,
+    };
+    info!("Backfilling {} lines", found.len());
+    for line in found {
+        if !replier.send(Replies::History(line)).await {
+            return;
+        }
+    }
+    replier.send(Replies::Done).await;
+}
+
+// when the file being tailed has too few lines, the backfill goes on with the files rotated away
+// before it, newest first and decompressed if need be
+async fn read_history(search: &FileSearch, position: &Checkpoint, lines: Option<usize>, since: Option<NaiveDateTime>) -> Result<Vec<String>, ClientError> {
+    let limit = lines.unwrap_or(MAX_BACKFILL_LINES).min(MAX_BACKFILL_LINES);
+    let (mut found, mut reached_since) = read_back(position, limit, since).await?;
+    if found.len() >= limit || reached_since {
+        return Ok(found);
+    }
+
+    let is_current = |path: &str, identity: FileIdentity| match position.get_identity() {
+        Some(current) => current == identity,
+        None => path == position.get_path(),
+    };
+    let mut older = search.candidates(None)?;
+    let current_modified = older.iter()
+        .find(|candidate| is_current(&candidate.file().path, candidate.file().identity))
+        .map(|candidate| candidate.modified());
+    older.retain(|candidate| !is_current(&candidate.file().path, candidate.file().identity)
+        && current_modified.is_none_or(|modified| candidate.modified() <= modified));
+    older.sort_by_key(|candidate| Reverse(candidate.modified()));
+
+    for candidate in older {
+        let path = &candidate.file().path;
+        let lines = match read_tail(path, limit - found.len(), since).await {
+            Ok((lines, reached)) => {
+                reached_since = reached;
+                lines
+            }
+            // what was found so far is still worth sending
+            Err(e) => {
+                error!("Cannot backfill from {}: {}", path, e);
+                break;
+            }
+        };
+        found.splice(0..0, lines);
+        if found.len() >= limit || reached_since {
+            break;
+        }
+    }
+    Ok(found)
+}
+
+// the last lines of a whole file, read from the start since compressed files can't be read backward,
+// and whether a line before since was seen
+async fn read_tail(path: &str, limit: usize, since: Option<NaiveDateTime>) -> Result<(Vec<String>, bool), ClientError> {
+    let mut reader = LineReader::open(path).await?;
+    let mut found = VecDeque::new();
+    let mut reached_since = false;
+    let mut last_timestamp = None;
+    while let Some(line) = reader.next_line().await? {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(timestamp) = timestamp(&line) {
+            last_timestamp = Some(timestamp);
+        }
+        // the lines before are older still
+        if since.is_some_and(|since| last_timestamp.is_some_and(|timestamp| timestamp < since)) {
+            reached_since = true;
+            found.clear();
+            continue;
+        }
+        if found.len() >= limit {
+            found.pop_front();
+        }
+        found.push_back(line);
+    }
+    Ok((found.into(), reached_since))
+}
+
+// reads the file backward from the position, stopping after limit lines or at the first line with a
+// timestamp before since, lines without one are kept, and tells whether that line was reached
+async fn read_back(position: &Checkpoint, limit: usize, since: Option<NaiveDateTime>) -> Result<(Vec<String>, bool), ClientError> {
+    let path = position.get_path();
+    let mut file = File::open(path).await
+        .map_err(|source| ClientError::Open { path: path.to_string(), source })?;
+    let metadata = file.metadata().await
+        .map_err(|source| ClientError::Metadata { path: path.to_string(), source })?;
+    if position.get_identity().is_some_and(|identity| identity != FileIdentity::of(&metadata)) {
+        return Err(ClientError::Replaced(path.to_string()));
+    }
+
+    // newest first until the end
+    let mut found = vec![];
+    // the start of the oldest line read so far, it may go on in the chunk before
+    let mut partial = vec![];
+    let mut end = position.get_position().min(metadata.len());
+    let mut reached_since = false;
+    'CHUNKS: while end > 0 && found.len() < limit {
+        let start = end.saturating_sub(CHUNK_SIZE);
+        let mut chunk = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start)).await
+            .map_err(|source| ClientError::Seek { path: path.to_string(), source })?;
+        file.read_exact(&mut chunk).await
+            .map_err(|source| ClientError::Read { path: path.to_string(), source })?;
+        chunk.append(&mut partial);
+
+        let mut segments: Vec<&[u8]> = chunk.split(|byte| *byte == b'\n').collect();
+        if start > 0 {
+            partial = segments.remove(0).to_vec();
+        }
+        for segment in segments.into_iter().rev() {
+            let line = String::from_utf8_lossy(segment).trim_end_matches('\r').to_string();
+            if line.is_empty() {
+                continue;
+            }
+            if since.is_some_and(|since| timestamp(&line).is_some_and(|timestamp| timestamp < since)) {
+                reached_since = true;
+                break 'CHUNKS;
+            }
+            found.push(line);
+            if found.len() >= limit {
+                break 'CHUNKS;
+            }
+        }
+        end = start;
+    }
+
+    found.reverse();
+    Ok((found, reached_since))
+}
+
+// the timestamp a line starts with, like 2026-10-19T12:00:00 or [2026-10-19 12:00:00
+pub fn timestamp(line: &str) -> Option<NaiveDateTime> {
+    let line = line.trim_start_matches('[');
+    let prefix = line.get(..19)?;
+    NaiveDateTime::parse_from_str(prefix, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(prefix, "%Y-%m-%d %H:%M:%S"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    struct TempFile(String);
+
+    impl TempFile {
+        fn new(name: &str, content: &[u8]) -> Self {
+            let path = env::temp_dir().join(format!("fefs-history-{}-{}", process::id(), name));
+            fs::write(&path, content).unwrap();
+            Self(path.to_string_lossy().to_string())
+        }
+
+        fn at(&self, position: u64) -> Checkpoint {
+            Checkpoint::new(self.0.clone(), None, position)
+        }
+
+        fn at_end(&self) -> Checkpoint {
+            self.at(fs::metadata(&self.0).unwrap().len())
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, 19).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn last_lines_oldest_first() {
+        let file = TempFile::new("last", b"one\ntwo\n\nthree\nfour\n");
+
+        assert_eq!(read_back(&file.at_end(), 2, None).await.unwrap(), (vec!["three".to_string(), "four".to_string()], false));
+        let (lines, _) = read_back(&file.at_end(), 10, None).await.unwrap();
+        assert_eq!(lines, vec!["one", "two", "three", "four"]);
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_position() {
+        let file = TempFile::new("position", b"one\ntwo\nthree\nfour\n");
+
+        let (lines, _) = read_back(&file.at(8), 10, None).await.unwrap();
+        assert_eq!(lines, vec!["one", "two"]);
+        // the tailer already sent the start of a line it's in the middle of
+        let (lines, _) = read_back(&file.at(10), 10, None).await.unwrap();
+        assert_eq!(lines, vec!["one", "two", "th"]);
+        let (lines, _) = read_back(&file.at(1_000), 10, None).await.unwrap();
+        assert_eq!(lines, vec!["one", "two", "three", "four"]);
+        let (lines, _) = read_back(&file.at(0), 10, None).await.unwrap();
+        assert!(lines.is_empty());
+    }
+
+    #[tokio::test]
+    async fn lines_across_chunks() {
+        let lines: Vec<String> = (0..500).map(|i| format!("{:04} {}", i, "x".repeat(i * 7 % 1_500))).collect();
+        let file = TempFile::new("chunks", (lines.join("\n") + "\n").as_bytes());
+        assert!(file.at_end().get_position() > 3 * CHUNK_SIZE);
+
+        let (found, reached_since) = read_back(&file.at_end(), MAX_BACKFILL_LINES, None).await.unwrap();
+        assert_eq!(found, lines);
+        assert!(!reached_since);
+        let (found, _) = read_back(&file.at_end(), 120, None).await.unwrap();
+        assert_eq!(found, lines[380..]);
+    }
+
+    #[tokio::test]
+    async fn line_longer_than_a_chunk() {
+        let long = "y".repeat(2 * CHUNK_SIZE as usize + 10);
+        let file = TempFile::new("long", format!("first\n{}\nlast\n", long).as_bytes());
+
+        let (found, _) = read_back(&file.at_end(), 10, None).await.unwrap();
+        assert_eq!(found, vec!["first".to_string(), long, "last".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn since_cuts_off_older_lines() {
+        let file = TempFile::new("since", b"2026-10-19T10:00:00 old\n\
+            [2026-10-19 11:00:00] older\n\
+            \tat a stack frame\n\
+            2026-10-19T12:00:00 new\n\
+            no timestamp\n\
+            2026-10-19T13:00:00 newest\n");
+
+        let (found, reached_since) = read_back(&file.at_end(), 10, Some(at(11, 30))).await.unwrap();
+        assert_eq!(found, vec!["\tat a stack frame", "2026-10-19T12:00:00 new", "no timestamp", "2026-10-19T13:00:00 newest"]);
+        assert!(reached_since);
+
+        let (found, reached_since) = read_back(&file.at_end(), 10, Some(at(9, 0))).await.unwrap();
+        assert_eq!(found.len(), 6);
+        assert!(!reached_since);
+    }
+
+    #[tokio::test]
+    async fn crlf_line_endings() {
+        let file = TempFile::new("crlf", b"one\r\ntwo\r\n\r\nthree\r\n");
+
+        let (found, _) = read_back(&file.at_end(), 10, None).await.unwrap();
+        assert_eq!(found, vec!["one", "two", "three"]);
+    }
+
+    #[tokio::test]
+    async fn missing_file_is_an_error() {
+        let checkpoint = Checkpoint::new(env::temp_dir().join("fefs-history-missing").to_string_lossy().to_string(), None, 10);
+        assert!(matches!(read_back(&checkpoint, 10, None).await, Err(ClientError::Open { .. })));
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp("2026-10-19T12:30:00 INFO started"), Some(at(12, 30)));
+        assert_eq!(timestamp("2026-10-19 12:30:00,123 INFO started"), Some(at(12, 30)));
+        assert_eq!(timestamp("[2026-10-19 12:30:00] started"), Some(at(12, 30)));
+        assert_eq!(timestamp("2026-10-19T12:30:00.123Z"), Some(at(12, 30)));
+        assert_eq!(timestamp("2026-10-19T12:30:00"), Some(at(12, 30)));
+    }
+
+    #[test]
+    fn no_timestamp() {
+        assert_eq!(timestamp(""), None);
+        assert_eq!(timestamp("2026-10-19"), None);
+        assert_eq!(timestamp("INFO 2026-10-19T12:30:00"), None);
+        assert_eq!(timestamp("2026-13-19T12:30:00 bad month"), None);
+        assert_eq!(timestamp("2026/10/19 12:30:00"), None);
+        // the 19th byte is inside a character
+        assert_eq!(timestamp("2026-10-19T12:30:0é"), None);
+    }
+}
@@ -0,0 +1,58 @@
+use std::fs::Metadata;
+
+use serde::{Deserialize, Serialize};
+
+// which file a path points at, unlike the creation time this is known on every unix filesystem
+// and changes when a file is rotated by renaming it and creating a new one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileIdentity {
+    dev: u64,
+    inode: u64
+}
+
+impl FileIdentity {
+    #[cfg(unix)]
+    pub fn of(metadata: &Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        Self { dev: metadata.dev(), inode: metadata.ino() }
+    }
+
+    // without inodes the creation time, or failing that the modification time, has to do
+    #[cfg(not(unix))]
+    pub fn of(metadata: &Metadata) -> Self {
+        let time = metadata.created().or_else(|_| metadata.modified()).ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+        Self { dev: 0, inode: time }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn rotation_changes_the_identity_of_the_path() {
+        let dir = env::temp_dir().join(format!("fefs-identity-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (current, rotated) = (dir.join("app.log"), dir.join("app.log.1"));
+        fs::write(&current, "first\n").unwrap();
+        let before = FileIdentity::of(&fs::metadata(&current).unwrap());
+        fs::write(&current, "first\nsecond\n").unwrap();
+        let written = FileIdentity::of(&fs::metadata(&current).unwrap());
+
+        fs::rename(&current, &rotated).unwrap();
+        fs::write(&current, "").unwrap();
+        let renamed = FileIdentity::of(&fs::metadata(&rotated).unwrap());
+        let replaced = FileIdentity::of(&fs::metadata(&current).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(before, written);
+        assert_eq!(before, renamed);
+        assert_ne!(before, replaced);
+    }
+}
@@ -0,0 +1,161 @@
+use std::env;
+
+use serde_json::Value;
+
+use super::validation::ConfigurationIssue;
+
+// replaces ${VAR} and ${VAR:-default} with environment variables in every string of the value,
+// $${ is left as a literal ${
+pub fn interpolate(value: &mut Value, path: &str) -> Vec<ConfigurationIssue> {
+    let mut issues = vec![];
+    interpolate_value(value, path, &mut issues);
+    issues
+}
+
+fn interpolate_value(value: &mut Value, path: &str, issues: &mut Vec<ConfigurationIssue>) {
+    match value {
+        Value::String(string) => match interpolate_str(string) {
+            Ok(interpolated) => *value = interpolated_value(string, interpolated),
+            Err(message) => issues.push(ConfigurationIssue::new(path, message)),
+        },
+        Value::Array(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                interpolate_value(value, &format!("{}[{}]", path, index), issues);
+            }
+        }
+        Value::Object(values) => {
+            for (key, value) in values.iter_mut() {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                interpolate_value(value, &path, issues);
+            }
+        }
+        _ => {}
+    }
+}
+
+// a string that is nothing but one variable becomes a number when the variable holds one, so port: "${PORT}" works,
+// any other text around it keeps the result a string
+fn interpolated_value(string: &str, interpolated: String) -> Value {
+    let whole = string.starts_with("${") && string.find('}') == Some(string.len() - 1);
+    match interpolated.parse::<serde_json::Number>() {
+        Ok(number) if whole => Value::Number(number),
+        _ => Value::String(interpolated),
+    }
+}
+
+fn interpolate_str(string: &str) -> Result<String, String> {
+    let mut interpolated = String::with_capacity(string.len());
+    let mut rest = string;
+    while let Some(start) = rest.find('$') {
+        interpolated.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            interpolated.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        let expression = match rest.strip_prefix("${") {
+            Some(expression) => expression,
+            None => {
+                interpolated.push('$');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+
+        let end = expression.find('}').ok_or_else(|| format!("unclosed ${{ in {}", string))?;
+        let (name, default) = match expression[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&expression[..end], None),
+        };
+        // like the shell, the default is also used when the variable is set but empty
+        match (env::var(name).ok().filter(|value| !value.is_empty()), default) {
+            (Some(value), _) => interpolated.push_str(&value),
+            (None, Some(default)) => interpolated.push_str(default),
+            (None, None) => return Err(format!("environment variable {} is not set and has no default", name)),
+        }
+        rest = &expression[end + 1..];
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn variables_and_defaults() {
+        env::set_var("FEFS_INTERPOLATION_HOST", "collector");
+        env::set_var("FEFS_INTERPOLATION_EMPTY", "");
+        env::remove_var("FEFS_INTERPOLATION_UNSET");
+
+        assert_eq!(interpolate_str("${FEFS_INTERPOLATION_HOST}:8080").unwrap(), "collector:8080");
+        assert_eq!(interpolate_str("${FEFS_INTERPOLATION_HOST:-localhost}").unwrap(), "collector");
+        assert_eq!(interpolate_str("${FEFS_INTERPOLATION_UNSET:-localhost}").unwrap(), "localhost");
+        assert_eq!(interpolate_str("${FEFS_INTERPOLATION_EMPTY:-localhost}").unwrap(), "localhost");
+        assert_eq!(interpolate_str("${FEFS_INTERPOLATION_UNSET:-}").unwrap(), "");
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(interpolate_str("no variables").unwrap(), "no variables");
+        assert_eq!(interpolate_str("costs $5 or $").unwrap(), "costs $5 or $");
+        assert_eq!(interpolate_str("$${HOME}").unwrap(), "${HOME}");
+    }
+
+    #[test]
+    fn errors() {
+        env::remove_var("FEFS_INTERPOLATION_MISSING");
+        assert!(interpolate_str("${FEFS_INTERPOLATION_MISSING}").unwrap_err().contains("FEFS_INTERPOLATION_MISSING"));
+        assert!(interpolate_str("${FEFS_INTERPOLATION_HOST").unwrap_err().starts_with("unclosed"));
+    }
+
+    #[test]
+    fn every_string_is_interpolated() {
+        env::set_var("FEFS_INTERPOLATION_POD", "pod-0");
+        env::remove_var("FEFS_INTERPOLATION_NOWHERE");
+        env::set_var("FEFS_INTERPOLATION_PORT", "9090");
+        let mut value = json!({
+            "app_name": { "MultiPod": { "application": "app", "pod_name": "${FEFS_INTERPOLATION_POD}" } },
+            "server_port": "${FEFS_INTERPOLATION_PORT}",
+            "servers": [{ "host": "${FEFS_INTERPOLATION_NOWHERE}", "port": 8080 }],
+            "channel_buffer": 10
+        });
+
+        let issues = interpolate(&mut value, "configs[0]");
+        assert_eq!(value["app_name"]["MultiPod"]["pod_name"], "pod-0");
+        assert_eq!(value["channel_buffer"], 10);
+        assert_eq!(value["server_port"], 9090);
+        let paths: Vec<&str> = issues.iter().map(ConfigurationIssue::path).collect();
+        assert_eq!(paths, vec!["configs[0].servers[0].host"]);
+    }
+
+    #[test]
+    fn whole_numeric_values_become_numbers() {
+        env::set_var("FEFS_INTERPOLATION_NUMBER", "8080");
+        env::set_var("FEFS_INTERPOLATION_ZEROS", "007");
+        env::remove_var("FEFS_INTERPOLATION_ABSENT");
+
+        let mut value = json!({
+            "port": "${FEFS_INTERPOLATION_NUMBER}",
+            "jitter": "${FEFS_INTERPOLATION_ABSENT:-0.5}",
+            "uri": "localhost:${FEFS_INTERPOLATION_NUMBER}",
+            "pod": "${FEFS_INTERPOLATION_ZEROS}",
+            "escaped": "${FEFS_INTERPOLATION_NUMBER}${FEFS_INTERPOLATION_ABSENT:-}",
+            "literal": "8080"
+        });
+        assert_eq!(interpolate(&mut value, ""), vec![]);
+        assert_eq!(value, json!({
+            "port": 8080,
+            "jitter": 0.5,
+            "uri": "localhost:8080",
+            "pod": "007",
+            "escaped": "8080",
+            "literal": "8080"
+        }));
+    }
+}
@@ -0,0 +1,130 @@
+use std::{fmt::{Display, Write}, sync::Arc};
+
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::Applicatiton;
+
+use super::{status::{ConnectionState, Statuses}, telemetry::{Counters, Tailers}};
+
+// reads one of the counters of a tailer
+type CounterValue = fn(&Counters) -> u64;
+
+const STATES: [ConnectionState; 4] = [ConnectionState::Connecting, ConnectionState::Connected, ConnectionState::Paused, ConnectionState::BackingOff];
+
+// in the prometheus text format, applications are labelled with their name and pod, pod is empty for
+// single pod applications
+#[get("/metrics")]
+pub async fn get_metrics(statuses: web::Data<Arc<Statuses>>, tailers: web::Data<Arc<Tailers>>) -> impl Responder {
+    let tailers: Vec<_> = tailers.lock().await.iter()
+        .map(|(application, tailer)| (application.clone(), tailer.clone()))
+        .collect();
+    let statuses: Vec<_> = statuses.lock().await.values().cloned().collect();
+    let mut out = String::new();
+
+    let counters: [(&str, &str, CounterValue); 4] = [
+        ("fefs_lines_read_total", "Lines read from the tailed files.", Counters::get_lines_read),
+        ("fefs_bytes_read_total", "Bytes read from the tailed files.", Counters::get_bytes_read),
+        ("fefs_lines_sent_total", "Lines sent to servers.", Counters::get_lines_sent),
+        ("fefs_lines_dropped_total", "Lines that never reached a server.", Counters::get_lines_dropped),
+    ];
+    for (name, help, value) in counters {
+        family(&mut out, name, "counter", help);
+        for (application, tailer) in tailers.iter() {
+            sample(&mut out, name, &application_labels(application), value(tailer.get_counters()));
+        }
+    }
+
+    family(&mut out, "fefs_tailer_offset_bytes", "gauge", "How far the tailed file was read.");
+    for (application, tailer) in tailers.iter() {
+        if let Some(position) = tailer.position() {
+            let mut labels = application_labels(application);
+            labels.push(("file", position.get_path().to_string()));
+            sample(&mut out, "fefs_tailer_offset_bytes", &labels, position.get_position());
+        }
+    }
+
+    family(&mut out, "fefs_reconnects_total", "counter", "Reconnects to a server.");
+    for status in statuses.iter() {
+        let mut labels = application_labels(status.application());
+        labels.push(("endpoint", status.endpoint().to_string()));
+        sample(&mut out, "fefs_reconnects_total", &labels, status.reconnects());
+    }
+
+    family(&mut out, "fefs_connection_state", "gauge", "1 for the state the connection to a server is in.");
+    for status in statuses.iter() {
+        for state in STATES {
+            let mut labels = application_labels(status.application());
+            labels.push(("endpoint", status.endpoint().to_string()));
+            labels.push(("state", format!("{:?}", state)));
+            sample(&mut out, "fefs_connection_state", &labels, (status.state() == state) as u8);
+        }
+    }
+
+    HttpResponse::Ok()
+        .append_header(("content-type", "text/plain; version=0.0.4; charset=utf-8"))
+        .body(out)
+}
+
+fn application_labels(application: &Applicatiton) -> Vec<(&'static str, String)> {
+    vec![("application", application.name()), ("pod", application.pod_name().unwrap_or_default())]
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, String)], value: impl Display) {
+    let labels: Vec<String> = labels.iter()
+        .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+        .collect();
+    let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), value);
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test::{call_service, init_service, read_body, TestRequest}, App};
+
+    use crate::{client::{checkpoint::Checkpoint, requests::TailerHandle, status, telemetry}, MultiPodApplication};
+
+    use super::*;
+
+    #[test]
+    fn label_values_are_escaped() {
+        let mut out = String::new();
+        sample(&mut out, "fefs_test", &[("file", "C:\\logs\\\"app\".log\n".to_string())], 1);
+        assert_eq!(out, "fefs_test{file=\"C:\\\\logs\\\\\\\"app\\\".log\\n\"} 1\n");
+    }
+
+    #[actix_web::test]
+    async fn counters_offsets_and_states_are_exposed() {
+        let application = Applicatiton::MultiPod(MultiPodApplication::new("app".to_string(), "pod-0".to_string()));
+        let statuses = Arc::new(status::new_statuses());
+        status::set_state(&statuses, &application, "ws://server:8080/ws", ConnectionState::Paused).await;
+        let tailers = Arc::new(telemetry::new_tailers());
+        let (handle, progress, _switches) = TailerHandle::new();
+        handle.get_counters().read(3, 42);
+        progress.send(Some(Checkpoint::new("/var/log/app.log".to_string(), None, 42))).unwrap();
+        tailers.lock().await.insert(application, handle);
+
+        let app = init_service(App::new()
+            .app_data(web::Data::new(statuses))
+            .app_data(web::Data::new(tailers))
+            .service(get_metrics)).await;
+        let response = call_service(&app, TestRequest::get().uri("/metrics").to_request()).await;
+        assert!(response.status().is_success());
+        let body = String::from_utf8(read_body(response).await.to_vec()).unwrap();
+
+        let labels = "application=\"app\",pod=\"pod-0\"";
+        assert!(body.contains("# TYPE fefs_lines_read_total counter\n"));
+        assert!(body.contains(&format!("fefs_lines_read_total{{{}}} 3\n", labels)));
+        assert!(body.contains(&format!("fefs_bytes_read_total{{{}}} 42\n", labels)));
+        assert!(body.contains(&format!("fefs_tailer_offset_bytes{{{},file=\"/var/log/app.log\"}} 42\n", labels)));
+        assert!(body.contains(&format!("fefs_connection_state{{{},endpoint=\"ws://server:8080/ws\",state=\"Paused\"}} 1\n", labels)));
+        assert!(body.contains(&format!("fefs_connection_state{{{},endpoint=\"ws://server:8080/ws\",state=\"Connected\"}} 0\n", labels)));
+    }
+}
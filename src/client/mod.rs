@@ -1,155 +1,486 @@
-use std::{fs, path::Path, time::{Duration, SystemTime}};
+use std::{fs, path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant}};
 
+use checkpoint::Checkpoint;
+use compressed::FileCompression;
 use configuration::LogConfiguration;
-use log::{error, info};
-use tokio::{fs::File, io::{AsyncBufReadExt, AsyncSeekExt, BufReader}, sync::mpsc::Sender, time::{self, sleep}};
+use context::ClientContext;
+use error::ClientError;
+use identity::FileIdentity;
+use log::{debug, error, info};
+use requests::Switch;
+use selection::{Candidate, FileSelection};
+use telemetry::Counters;
+use tokio::{fs::File, io::{AsyncBufReadExt, AsyncSeekExt, BufReader}, sync::{mpsc::{Receiver, Sender}, watch}, time::{self, sleep}};
 
-use crate::message::{DataMessage, Message, SystemMessage, SystemMessages};
+use crate::{message::{DataMessage, Message, Replies, SystemMessage, SystemMessages}, Applicatiton};
 
 pub mod process;
 pub mod configuration;
+pub mod backoff;
+pub mod status;
+pub mod supervisor;
+pub mod validation;
+pub mod interpolation;
+pub mod shutdown;
+pub mod checkpoint;
+pub mod context;
+pub mod error;
+pub mod identity;
+pub mod selection;
+pub mod paths;
+pub mod connection;
+pub mod backlog;
+pub mod history;
+pub mod requests;
+pub mod search;
+pub mod compressed;
+pub mod telemetry;
+pub mod metrics;
+pub mod console;
 
 pub struct FileTailer {
     reader: BufReader<File>,
     path: String,
+    search: FileSearch,
+    identity: FileIdentity,
+    // how long the current file has to go without new lines before a newer file takes over
+    quiet_period: Duration,
+    last_read: Instant,
+    last_rollover_check: Instant,
+    // the server switched to this file, so it's tailed until it goes away instead of rolling over
+    pinned: bool,
+    // how far the current file was read, published for backfills to read back from
+    position: u64,
+    progress: watch::Sender<Option<Checkpoint>>,
+    counters: Arc<Counters>
+}
+
+// how the file to tail is picked out of the log file directories
+struct FileSearch {
+    dirs: Vec<PathBuf>,
     regex: String,
-    dir: String,
-    created_date_time: SystemTime
+    selection: FileSelection,
+    max_age: Option<Duration>
 }
 
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const ROLLOVER_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 impl FileTailer {
-    pub async fn new(regex: String, dir: String) -> Option<Self> {
-        let mut files = fs::read_dir(dir.clone()).unwrap();
-        loop {
-            let file = files.next();
-            match file {
-                Some(file) => {
-                    let file = file.unwrap();
-                    let file_name = file.file_name().into_string().unwrap();
-                    if match_file_name(&file_name, &regex) {
-                        info!("Found file: {}", file_name);
-                        let file = File::open(&file_name).await.unwrap();
-                        let created_date_time = file.metadata().await.unwrap().created().unwrap();
-                        let reader = BufReader::new(file);
-                        return Some(Self { reader, path: file_name, regex, dir, created_date_time })
-                    }
-                }
-                None => {
-                    break;
-                }
-            }
+    pub async fn new(config: &LogConfiguration, progress: watch::Sender<Option<Checkpoint>>, counters: Arc<Counters>) -> Result<Option<Self>, ClientError> {
+        let search = FileSearch::new(config);
+        match search.open(None).await? {
+            Some((reader, path, identity)) => Ok(Some(Self {
+                reader,
+                path,
+                search,
+                identity,
+                quiet_period: config.get_rollover_quiet_period(),
+                last_read: Instant::now(),
+                last_rollover_check: Instant::now(),
+                pinned: false,
+                position: 0,
+                progress,
+                counters
+            })),
+            None => Ok(None),
         }
-        None
     }
 
+    // recoverable errors are reported and retried, the rest end tailing and are returned, on shutdown
+    // the position is saved however tailing ended
+    pub async fn tail(&mut self, tx: Sender<Message>, config: LogConfiguration, context: &ClientContext, switches: &mut Receiver<Switch>) -> Result<(), ClientError> {
+        let application = config.get_application();
+        self.seek_to_start(&application, context).await?;
 
-    pub async fn tail(&mut self, tx: Sender<Message>, config: LogConfiguration) {
-
-        self.reader.seek(std::io::SeekFrom::End(0)).await.unwrap();
+        let result = self.follow(&tx, &config, context, switches).await;
+        if context.is_shutting_down() {
+            self.save_checkpoint(&application, context).await;
+        }
+        info!("Tailing stopped");
+        match result {
+            // with no server to send to the channel is closed on shutdown
+            Err(ClientError::ChannelClosed) if context.is_shutting_down() => Ok(()),
+            result => result,
+        }
+    }
 
-        let sys_message = Message::System(SystemMessage::new(config.get_application(), SystemMessages::TailingStarted));
-        tx.send(sys_message).await.unwrap();
+    async fn follow(
+        &mut self,
+        tx: &Sender<Message>,
+        config: &LogConfiguration,
+        context: &ClientContext,
+        switches: &mut Receiver<Switch>
+    ) -> Result<(), ClientError> {
+        send_system_message(tx, config, SystemMessages::TailingStarted).await?;
 
         info!("Tailing file: {}", self.path);
 
         let mut last_line = String::new();
         let mut end_by_new_line = true;
+        // the file that was just rotated away, so it isn't picked up again if its new name still matches
+        let mut rotated = None;
 
-        //TODO break on SIGTERM
         'OUTER: loop {
             loop {
-                if tx.is_closed() {
+                if tx.is_closed() || context.is_shutting_down() {
                     break 'OUTER;
                 }
 
-                if !self.read_line(&tx, &mut last_line, &mut end_by_new_line, &config).await {
+                if let Ok(switch) = switches.try_recv() {
+                    if self.switch(switch, tx, config).await? {
+                        last_line.clear();
+                        end_by_new_line = true;
+                    }
+                }
+
+                let file_gone = match self.read_line(tx, &mut last_line, &mut end_by_new_line, config).await {
+                    Ok(true) => false,
+                    Ok(false) => {
+                        rotated = Some(self.identity);
+                        true
+                    }
+                    // no one is left to report to
+                    Err(ClientError::ChannelClosed) => return Err(ClientError::ChannelClosed),
+                    Err(e) => {
+                        rotated = None;
+                        report(tx, config, e).await?;
+                        time::sleep(RETRY_DELAY).await;
+                        true
+                    }
+                };
+                if file_gone {
                     last_line.clear();
                     end_by_new_line = true;
-                    let sys_message = Message::System(SystemMessage::new(config.get_application(), SystemMessages::FileRemoved));
                     if tx.is_closed() {
                         break 'OUTER;
-                    }   
-                    tx.send(sys_message).await.unwrap();
+                    }
+                    send_system_message(tx, config, SystemMessages::FileRemoved).await?;
                     break;
                 }
+
+                match self.roll_over().await {
+                    Ok(Some(previous)) => {
+                        last_line.clear();
+                        end_by_new_line = true;
+                        let current = self.path.clone();
+                        send_system_message(tx, config, SystemMessages::NewFileFound { previous, current }).await?;
+                    }
+                    Ok(None) => {}
+                    Err(e) => report(tx, config, e).await?,
+                }
             }
 
             loop {
-                if tx.is_closed() {
+                if tx.is_closed() || context.is_shutting_down() {
                     break 'OUTER;
                 }
 
-                if self.find_next_file().await {
-                    let sys_message = Message::System(SystemMessage::new(config.get_application(), SystemMessages::NewFileFound));
-                    tx.send(sys_message).await.unwrap();
-                    break;
+                if let Ok(switch) = switches.try_recv() {
+                    if self.switch(switch, tx, config).await? {
+                        break;
+                    }
+                }
+
+                let previous = self.path.clone();
+                match self.find_next_file(rotated).await {
+                    Ok(true) => {
+                        let current = self.path.clone();
+                        send_system_message(tx, config, SystemMessages::NewFileFound { previous, current }).await?;
+                        break;
+                    }
+                    Ok(false) => time::sleep(Duration::from_millis(100)).await,
+                    Err(e) => {
+                        report(tx, config, e).await?;
+                        time::sleep(RETRY_DELAY).await;
+                    }
                 }
-                time::sleep(Duration::from_millis(100)).await;
             }
         }
 
-        info!("Tailing stopped");
+        Ok(())
+    }
+
+    async fn seek_to_start(&mut self, application: &Applicatiton, context: &ClientContext) -> Result<(), ClientError> {
+        let checkpoint = context.get_checkpoint_dir().and_then(|dir| checkpoint::load(dir, application));
+        let length = self.reader.get_ref().metadata().await.map(|metadata| metadata.len()).unwrap_or_default();
+        let position = match checkpoint {
+            // a shorter or different file was truncated or replaced since, its old position means nothing
+            Some(checkpoint) if checkpoint.get_path() == self.path
+                && checkpoint.get_identity().is_none_or(|identity| identity == self.identity)
+                && checkpoint.get_position() <= length => {
+                info!("Resuming {} from position {}", self.path, checkpoint.get_position());
+                std::io::SeekFrom::Start(checkpoint.get_position())
+            }
+            _ => std::io::SeekFrom::End(0),
+        };
+        let position = self.reader.seek(position).await
+            .map_err(|source| ClientError::Seek { path: self.path.clone(), source })?;
+        self.set_position(position);
+        Ok(())
+    }
+
+    // moves to a file the server picked, answers the server and returns whether the file changed
+    async fn switch(&mut self, switch: Switch, tx: &Sender<Message>, config: &LogConfiguration) -> Result<bool, ClientError> {
+        let previous = match self.open_pinned(switch.get_path()).await {
+            Ok(previous) => previous,
+            Err(e) => {
+                switch.get_replier().fail(e).await;
+                return Ok(false);
+            }
+        };
+        let current = self.path.clone();
+        info!("Switched from {} to {}", previous, current);
+        switch.get_replier().send(Replies::Done).await;
+        send_system_message(tx, config, SystemMessages::NewFileFound { previous, current }).await?;
+        Ok(true)
+    }
+
+    // read from its end like the file tailing starts with, returns the previous path
+    async fn open_pinned(&mut self, path: &str) -> Result<String, ClientError> {
+        let candidate = self.search.candidate(path)?;
+        if FileCompression::of(path).is_some() {
+            return Err(ClientError::Compressed(path.to_string()));
+        }
+        // the file that would be picked anyway needn't be held on to
+        let pinned = self.search.find(None)?
+            .is_none_or(|selected| selected.file().identity != candidate.file().identity);
+        let (mut reader, path, identity) = self.search.open_candidate(candidate).await?;
+        let position = reader.seek(std::io::SeekFrom::End(0)).await
+            .map_err(|source| ClientError::Seek { path: path.clone(), source })?;
+        self.reader = reader;
+        self.identity = identity;
+        self.pinned = pinned;
+        self.last_read = Instant::now();
+        let previous = std::mem::replace(&mut self.path, path);
+        self.set_position(position);
+        Ok(previous)
+    }
+
+    fn set_position(&mut self, position: u64) {
+        self.position = position;
+        self.progress.send_replace(Some(Checkpoint::new(self.path.clone(), Some(self.identity), position)));
+    }
+
+    async fn save_checkpoint(&mut self, application: &Applicatiton, context: &ClientContext) {
+        let dir = match context.get_checkpoint_dir() {
+            Some(dir) => dir,
+            None => return,
+        };
+        let position = match self.reader.stream_position().await {
+            Ok(position) => position,
+            Err(e) => {
+                error!("Cannot get the read position of {}: {}", self.path, e);
+                return;
+            }
+        };
+        match checkpoint::save(dir, application, &Checkpoint::new(self.path.clone(), Some(self.identity), position)) {
+            Ok(_) => info!("Saved checkpoint for {} at position {}", self.path, position),
+            Err(e) => error!("Cannot save checkpoint for {}: {}", self.path, e),
+        }
     }
 
-    async fn read_line(&mut self, tx: &Sender<Message>, last_line: &mut String, end_by_new_line: &mut bool, config: &LogConfiguration) -> bool {
+    // false once the path no longer leads to the file being read
+    async fn read_line(&mut self, tx: &Sender<Message>, last_line: &mut String, end_by_new_line: &mut bool, config: &LogConfiguration) -> Result<bool, ClientError> {
         let mut line = String::new();
-        let bytes_read = self.reader.read_line(&mut line).await.unwrap();
+        let bytes_read = self.reader.read_line(&mut line).await
+            .map_err(|source| ClientError::Read { path: self.path.clone(), source })?;
     
         if bytes_read == 0 {
             sleep(Duration::from_millis(100)).await;
-            let path = Path::new(self.path.as_str());
-            let exists = Path::exists(path);
-            if !exists {
-                info!("File removed: {}", self.path);
-                return false
-            }
-            let file = File::open(&self.path).await.unwrap();
-            let same_file = file.metadata().await.unwrap().created().unwrap() == self.created_date_time;
-            if !same_file {
+            let metadata = match tokio::fs::metadata(&self.path).await {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    info!("File removed: {}", self.path);
+                    return Ok(false)
+                }
+                Err(source) => return Err(ClientError::Metadata { path: self.path.clone(), source }),
+            };
+            if FileIdentity::of(&metadata) != self.identity {
                 info!("File replaced: {}", self.path);
-                return false
+                return Ok(false)
+            }
+            // same file but shorter than what was read, it was truncated in place (copytruncate)
+            let position = self.reader.stream_position().await
+                .map_err(|source| ClientError::Seek { path: self.path.clone(), source })?;
+            if metadata.len() < position {
+                info!("File truncated: {}", self.path);
+                self.reader.seek(std::io::SeekFrom::Start(0)).await
+                    .map_err(|source| ClientError::Seek { path: self.path.clone(), source })?;
+                self.set_position(0);
             }
         } else {
-            process_line(line, &mut *last_line, &mut *end_by_new_line, &tx, &config).await;
+            self.last_read = Instant::now();
+            self.set_position(self.position + bytes_read as u64);
+            // the rest of a line cut short at the end of the file isn't another line
+            self.counters.read(line.ends_with('\n') as u64, bytes_read as u64);
+            process_line(line, &mut *last_line, &mut *end_by_new_line, tx, config).await?;
         }    
-        true
+        Ok(true)
     }
 
-    async fn find_next_file(&mut self) -> bool {
-        let mut files = fs::read_dir(self.dir.clone()).unwrap();
-        error!("Regex: {}", self.regex);
-        loop {
-            let file = files.next();
-            match file {
-                Some(file) => {
-                    let file = file.unwrap();
-                    let file_name = file.file_name().into_string().unwrap();
-                    info!("Checking file: {}", file_name);
-                    if match_file_name(&file_name, &self.regex) {
-                        info!("Found file: {}", file_name);
-                        let file = File::open(&file_name).await.unwrap();
-                        let created_date_time = file.metadata().await.unwrap().created().unwrap();
-                        self.created_date_time = created_date_time;
-                        self.reader = BufReader::new(file);
-                        self.path = file_name;
-                        return true
-                    }
-                }
-                None => {
-                    break;
+    // date stamped logs are never removed or replaced, so once the current file has been quiet for the
+    // quiet period and another file would be selected, that one takes over, returns the previous path
+    async fn roll_over(&mut self) -> Result<Option<String>, ClientError> {
+        if self.pinned || self.last_read.elapsed() < self.quiet_period || self.last_rollover_check.elapsed() < ROLLOVER_CHECK_INTERVAL {
+            return Ok(None)
+        }
+        self.last_rollover_check = Instant::now();
+
+        let candidate = match self.search.find(None)? {
+            Some(candidate) if candidate.file().identity != self.identity => candidate,
+            _ => return Ok(None),
+        };
+        let (reader, path, identity) = self.search.open_candidate(candidate).await?;
+        info!("Rolled over from {} to {}", self.path, path);
+        self.reader = reader;
+        self.identity = identity;
+        self.last_read = Instant::now();
+        let previous = std::mem::replace(&mut self.path, path);
+        self.set_position(0);
+        Ok(Some(previous))
+    }
+
+    async fn find_next_file(&mut self, rotated: Option<FileIdentity>) -> Result<bool, ClientError> {
+        match self.search.open(rotated).await? {
+            Some((reader, path, identity)) => {
+                self.identity = identity;
+                self.reader = reader;
+                self.path = path;
+                self.last_read = Instant::now();
+                self.pinned = false;
+                self.set_position(0);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+// a file matching the regex, by its full path
+struct FoundFile {
+    path: String,
+    identity: FileIdentity,
+    size: u64
+}
+
+impl FileSearch {
+    fn new(config: &LogConfiguration) -> Self {
+        Self {
+            dirs: config.get_log_file_dirs().iter().map(|dir| paths::resolve_dir(dir)).collect(),
+            regex: config.get_log_file_name_regex(),
+            selection: config.get_file_selection(),
+            max_age: config.get_max_file_age()
+        }
+    }
+
+    // the file at the path, if it's one of the candidates
+    fn candidate(&self, path: &str) -> Result<Candidate<FoundFile>, ClientError> {
+        self.candidates(None)?.into_iter()
+            .find(|candidate| candidate.file().path == path)
+            .ok_or_else(|| ClientError::NotListed(path.to_string()))
+    }
+
+    // opens the selected file among those matching the regex, leaving out the one with the skip identity
+    async fn open(&self, skip: Option<FileIdentity>) -> Result<Option<(BufReader<File>, String, FileIdentity)>, ClientError> {
+        match self.find(skip)? {
+            Some(candidate) => Ok(Some(self.open_candidate(candidate).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn open_candidate(&self, candidate: Candidate<FoundFile>) -> Result<(BufReader<File>, String, FileIdentity), ClientError> {
+        let found = candidate.into_file();
+        let file = File::open(&found.path).await
+            .map_err(|source| ClientError::Open { path: found.path.clone(), source })?;
+        info!("Found file: {}", found.path);
+        Ok((BufReader::new(file), found.path, found.identity))
+    }
+
+    // candidates are compared by file name whichever directory they are in, compressed files are
+    // finished and never tailed
+    fn find(&self, skip: Option<FileIdentity>) -> Result<Option<Candidate<FoundFile>>, ClientError> {
+        let candidates = self.candidates(skip)?.into_iter()
+            .filter(|candidate| FileCompression::of(&candidate.file().path).is_none())
+            .collect();
+        Ok(self.selection.select(candidates, self.max_age))
+    }
+
+    // every file matching the regex, old ones included, a directory that can't be read is only an
+    // error when none of them can
+    fn candidates(&self, skip: Option<FileIdentity>) -> Result<Vec<Candidate<FoundFile>>, ClientError> {
+        let regex = regex::Regex::new(&self.regex)?;
+        let mut candidates = vec![];
+        let mut read_dir_error = None;
+        let mut read_any = false;
+        for dir in self.dirs.iter() {
+            match self.find_in(dir, &regex, skip, &mut candidates) {
+                Ok(()) => read_any = true,
+                Err(e) => {
+                    debug!("Skipping {}: {}", dir.display(), e);
+                    read_dir_error.get_or_insert(e);
                 }
             }
         }
-        false
+        if let (false, Some(e)) = (read_any, read_dir_error) {
+            return Err(e);
+        }
+        Ok(candidates)
+    }
+
+    fn find_in(&self, dir: &Path, regex: &regex::Regex, skip: Option<FileIdentity>, candidates: &mut Vec<Candidate<FoundFile>>) -> Result<(), ClientError> {
+        let files = fs::read_dir(dir)
+            .map_err(|source| ClientError::ReadDir { path: dir.display().to_string(), source })?;
+        for file in files {
+            let file = file.map_err(|source| ClientError::ReadDir { path: dir.display().to_string(), source })?;
+            let file_name = file.file_name().into_string()
+                .map_err(|file_name| ClientError::FileName(file_name.to_string_lossy().to_string()))?;
+            if !regex.is_match(&file_name) {
+                continue;
+            }
+            let path = file.path().display().to_string();
+            // follows symlinks, so current.log -> app-123.log is identified by app-123.log
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() => metadata,
+                Ok(_) => continue,
+                // deleted since the directory was read, or a dangling symlink
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(source) => return Err(ClientError::Metadata { path, source }),
+            };
+            let identity = FileIdentity::of(&metadata);
+            if Some(identity) == skip {
+                continue;
+            }
+            let modified = metadata.modified()
+                .map_err(|source| ClientError::Metadata { path: path.clone(), source })?;
+            candidates.push(Candidate::new(file_name, modified, FoundFile { path, identity, size: metadata.len() }));
+        }
+        Ok(())
+    }
+}
+
+async fn send_system_message(tx: &Sender<Message>, config: &LogConfiguration, message: SystemMessages) -> Result<(), ClientError> {
+    let sys_message = Message::System(SystemMessage::new(config.get_application(), message));
+    tx.send(sys_message).await.map_err(|_| ClientError::ChannelClosed)
+}
+
+// logs the error and lets the server know, hands it back when there is no point in retrying
+pub async fn report(tx: &Sender<Message>, config: &LogConfiguration, e: ClientError) -> Result<(), ClientError> {
+    error!("Error tailing {}: {}", config.get_application().name(), e);
+    let sent = send_system_message(tx, config, SystemMessages::Error(e.to_string())).await;
+    if e.is_recoverable() {
+        sent
+    } else {
+        Err(e)
     }
 }
 
-async fn process_line(mut line: String, last_line: &mut String, end_by_new_line: &mut bool, tx: &Sender<Message>, config: &LogConfiguration) {
+async fn process_line(mut line: String, last_line: &mut String, end_by_new_line: &mut bool, tx: &Sender<Message>, config: &LogConfiguration) -> Result<(), ClientError> {
     let replacent: &str = "👻🛸👻";
     line = line.replace('\n', replacent);
     let lines = line.split('👻');
     for mut line in lines {
-        if line.eq("") {
+        if line.is_empty() {
             continue;
         }
         if line.eq("🛸") {
@@ -161,27 +492,127 @@ async fn process_line(mut line: String, last_line: &mut String, end_by_new_line:
             false
         } else {
             last_line.push_str(line);
-            line = &last_line;
+            line = last_line;
             true
         };
         let message = DataMessage::new(line.to_string(), config.get_application(), append);
         let message = Message::Data(message);
-        if tx.is_closed() {
-            break;
-        }
-        match tx.send(message).await{
-            Ok(_) => {}
-            Err(e) => {
-                error!("Error sending message: {}", e);
-                break;
-            }
-        }
+        tx.send(message).await.map_err(|_| ClientError::ChannelClosed)?;
         info!("{}", line);
         *last_line = line.to_string();
     }
+    Ok(())
 }
 
-fn match_file_name(file_name: &str, regex: &str) -> bool {
-    let re = regex::Regex::new(regex).unwrap();
-    re.is_match(file_name)
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    fn config() -> LogConfiguration {
+        config_for(".", ".*")
+    }
+
+    fn config_for(dir: &str, regex: &str) -> LogConfiguration {
+        LogConfiguration::new(Applicatiton::SinglePod("app".to_string()), dir.to_string(), regex.to_string(), vec![])
+    }
+
+    #[tokio::test]
+    async fn errors_are_reported_to_the_server() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let missing = ClientError::Open { path: "app.log".to_string(), source: std::io::ErrorKind::NotFound.into() };
+        assert!(report(&tx, &config(), missing).await.is_ok());
+        let regex = ClientError::Regex(regex::Error::Syntax("unclosed group".to_string()));
+        assert!(matches!(report(&tx, &config(), regex).await, Err(ClientError::Regex(_))));
+
+        for expected in ["cannot open app.log", "invalid log file name regex"] {
+            match rx.recv().await.unwrap().system().map(SystemMessage::message) {
+                Some(SystemMessages::Error(error)) => assert!(error.starts_with(expected), "{}", error),
+                message => panic!("expected an error message, got {:?}", message),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn nothing_to_report_to() {
+        let (tx, rx) = mpsc::channel(10);
+        drop(rx);
+        let missing = ClientError::Open { path: "app.log".to_string(), source: std::io::ErrorKind::NotFound.into() };
+        assert!(matches!(report(&tx, &config(), missing).await, Err(ClientError::ChannelClosed)));
+    }
+
+    #[tokio::test]
+    async fn unusable_configuration_is_an_error() {
+        assert!(matches!(FileTailer::new(&config_for("/no/such/directory", ".*"), watch::channel(None).0, Default::default()).await, Err(ClientError::ReadDir { .. })));
+        assert!(matches!(FileTailer::new(&config_for(".", "("), watch::channel(None).0, Default::default()).await, Err(ClientError::Regex(_))));
+    }
+
+    async fn next_system_message(rx: &mut mpsc::Receiver<Message>) -> SystemMessages {
+        loop {
+            let message = time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+            if let Some(message) = message.system() {
+                return message.message().clone();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rolls_over_to_a_newer_file_once_the_current_one_is_quiet() {
+        let dir = std::env::temp_dir().join(format!("fefs-rollover-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (first, second) = (dir.join("app-9.log").display().to_string(), dir.join("app-10.log").display().to_string());
+        fs::write(&first, "").unwrap();
+
+        let config: LogConfiguration = serde_json::from_value(serde_json::json!({
+            "app_name": { "SinglePod": "app" },
+            "log_file_dir": dir,
+            "log_file_name_regex": r"^app-\d+\.log$",
+            "file_selection": "Natural",
+            "rollover_quiet_period_ms": 100,
+            "channel_buffer": 10
+        })).unwrap();
+        let (shutdown_tx, shutdown) = shutdown::Shutdown::new();
+        let context = ClientContext::new(std::sync::Arc::new(status::new_statuses()), std::sync::Arc::new(telemetry::new_tailers()), shutdown, None);
+        let mut tailer = FileTailer::new(&config, watch::channel(None).0, Default::default()).await.unwrap().unwrap();
+        let (tx, mut rx) = mpsc::channel(10);
+        let tailing = tokio::spawn(async move { tailer.tail(tx, config, &context, &mut mpsc::channel(1).1).await });
+
+        let started = next_system_message(&mut rx).await;
+        fs::write(&second, "").unwrap();
+        let rolled_over = next_system_message(&mut rx).await;
+        let _ = shutdown_tx.send(true);
+        let stopped = time::timeout(Duration::from_secs(5), tailing).await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(started, SystemMessages::TailingStarted));
+        match rolled_over {
+            SystemMessages::NewFileFound { previous, current } => assert_eq!((previous, current), (first, second)),
+            message => panic!("expected a rollover, got {:?}", message),
+        }
+        assert!(matches!(stopped, Ok(Ok(Ok(())))));
+    }
+
+    #[tokio::test]
+    async fn files_are_found_in_every_directory() {
+        let dir = std::env::temp_dir().join(format!("fefs-directories-test-{}", std::process::id()));
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(a.join("app-9.log"), "").unwrap();
+        fs::write(b.join("app-10.log"), "").unwrap();
+
+        let config: LogConfiguration = serde_json::from_value(serde_json::json!({
+            "app_name": { "SinglePod": "app" },
+            "log_file_dir": dir.join("missing"),
+            "log_file_dirs": [a, b],
+            "log_file_name_regex": r"^app-\d+\.log$",
+            "file_selection": "Natural",
+            "channel_buffer": 10
+        })).unwrap();
+        let tailer = FileTailer::new(&config, watch::channel(None).0, Default::default()).await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tailer.unwrap().unwrap().path, b.join("app-10.log").display().to_string());
+    }
+}
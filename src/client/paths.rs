@@ -0,0 +1,43 @@
+use std::{env, path::{self, PathBuf}};
+
+// expands a leading ~ to the home directory and makes a relative directory absolute against the
+// working directory, symlinks are left alone so a repointed link is noticed
+pub fn resolve_dir(dir: &str) -> PathBuf {
+    let expanded = match dir.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') || rest.starts_with(path::MAIN_SEPARATOR) => {
+            match home_dir() {
+                Some(home) => home.join(rest.trim_start_matches(['/', path::MAIN_SEPARATOR])),
+                None => PathBuf::from(dir),
+            }
+        }
+        _ => PathBuf::from(dir),
+    };
+    path::absolute(&expanded).unwrap_or(expanded)
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn home_directory() {
+        let home = home_dir().unwrap();
+        assert_eq!(resolve_dir("~"), path::absolute(&home).unwrap());
+        assert_eq!(resolve_dir("~/logs"), path::absolute(home.join("logs")).unwrap());
+        // only the current user's home is expanded
+        assert!(resolve_dir("~other/logs").ends_with("~other/logs"));
+    }
+
+    #[test]
+    fn relative_directories_are_made_absolute() {
+        assert_eq!(resolve_dir("logs"), env::current_dir().unwrap().join("logs"));
+        assert_eq!(resolve_dir("/var/log"), PathBuf::from("/var/log"));
+    }
+}
@@ -1,194 +1,404 @@
-use log::{debug, error, info, warn};
-use tokio::{sync::mpsc::Sender, time};
-use tokio_tungstenite::connect_async;
-use futures_util::{SinkExt, StreamExt};
-use tungstenite::{handshake::client::generate_key, http::Request, Message, Error};
+use std::sync::Arc;
 
-use crate::{client::FileTailer, message};
+use futures::future;
+use log::{debug, error, info};
+use tokio::{sync::{mpsc, watch}, time};
 
-use super::configuration::LogConfiguration;
+use crate::{client::{self, FileTailer}, message::{self, SystemMessage}};
 
-pub async fn file(config: LogConfiguration) {
-    loop {
-        process_until_error(config.clone()).await;
-        time::sleep(time::Duration::from_secs(20)).await;
+use super::{backlog::Backlog, console, backoff::Backoff, checkpoint::Checkpoint, configuration::{Delivery, LogConfiguration, ServerEndpoint}, context::ClientContext, requests::{self, Switch, TailerHandle}, status::{self, ConnectionState}, telemetry::{self, Counters, Rates}};
+
+// on shutdown the tailer stops first, whatever it already read is still delivered and each connection
+// ends with a Stop message and a close frame once the channel is empty
+pub async fn file(config: LogConfiguration, context: ClientContext) {
+    // the tailer lives as long as the configuration, so switching servers doesn't lose the read position
+    let (tx, rx) = mpsc::channel(config.get_channel_buffer());
+    let (handle, progress, switches) = TailerHandle::new();
+    telemetry::add_tailer(context.get_tailers(), &config.get_application(), handle.clone()).await;
+    let tailer = tail(config.clone(), tx, progress, switches, Arc::clone(handle.get_counters()), &context);
+
+    if let Some(format) = context.get_console() {
+        tokio::join!(tailer, console::print(rx, format));
+        return;
     }
-}
 
-//TODO fix the unwraps with actual errors
-async fn process_until_error(config: LogConfiguration) {
-    let host = config.get_server_host();
-    let port = config.get_server_port();
-    let path = config.get_server_path();
-    let host = format!("{}:{}", host, port);
-    let uri = format!("ws://{}/{}", host, path);
-
-    info!("connecting to {}", uri);
-    // Connect to WebSocket server
-    let request = Request::builder()
-        .uri(uri)
-        .header("Host", host)
-        .header("Sec-WebSocket-Key", generate_key())
-        .header("Sec-WebSocket-Version", "13")
-        .header("Upgrade", "websocket")
-        .header("Connection", "Upgrade")
-        .header("Application", config.get_application().to_string())
-        .body(())
-        .unwrap();
-    
-    let (ws_stream, _) = match connect_async(request).await {
-        Ok(data) => data,
-        Err(err) => {
-            error!("Error connecting to WebSocket server: {}", err);
-            return;
-        },
-    };
+    let servers = config.get_servers();
+    if servers.is_empty() {
+        error!("No server configured for {}", config.get_application().name());
+        return;
+    }
 
-    info!("webSocket connected");
-    
-    // Split the WebSocket stream
-    let (mut write, mut read) = ws_stream.split();
-    let (tx, mut rx) = tokio::sync::mpsc::channel(config.get_channel_buffer());
-    let (tx_client_abort, mut rx_client_abort) = tokio::sync::mpsc::channel::<()>(1);
-    let (tx_server_abort, mut rx_server_abort) = tokio::sync::mpsc::channel::<()>(1);
-    
-    // Spawn a task to handle incoming messages
-    let tx_clone = tx.clone();
-    let receive_task = tokio::spawn(async move {
-        let mut abort_send_task = true;
-        loop {
-            tokio::select! {
-                _ = rx_client_abort.recv() => {
-                    info!("client receive task aborted");
-                    abort_send_task = false;
-                    break;
-                },
-                message = read.next() => {
-                    if message.is_some() && !process_message(message.unwrap(), &tx_clone).await {
-                        break;
-                    }
+    match config.get_delivery() {
+        Delivery::Failover => {
+            // deliver only returns on shutdown, dropping rx then frees a tailer waiting for room in the
+            // channel while no server is reading it
+            let delivery = async {
+                let mut rx = rx;
+                deliver(&config, &servers, &mut rx, &handle, &context).await;
+            };
+            tokio::join!(tailer, delivery);
+        }
+        Delivery::Mirror => {
+            let mut senders = vec![];
+            let mut deliveries = vec![];
+            for server in servers {
+                let (server_tx, server_rx) = mpsc::channel(config.get_channel_buffer());
+                senders.push(server_tx);
+                let config = config.clone();
+                let context = context.clone();
+                let handle = handle.clone();
+                deliveries.push(async move {
+                    let mut server_rx = server_rx;
+                    deliver(&config, &[server], &mut server_rx, &handle, &context).await
+                });
+            }
+            tokio::join!(tailer, mirror(rx, senders, Arc::clone(handle.get_counters())), future::join_all(deliveries));
+        }
+    }
+}
+
+async fn tail(
+    config: LogConfiguration,
+    tx: mpsc::Sender<crate::message::Message>,
+    progress: watch::Sender<Option<Checkpoint>>,
+    mut switches: mpsc::Receiver<Switch>,
+    counters: Arc<Counters>,
+    context: &ClientContext
+) {
+    let mut shutdown = context.get_shutdown();
+    let mut file_tailer = loop {
+        let file_tailer = FileTailer::new(&config, progress.clone(), Arc::clone(&counters)).await;
+        match file_tailer {
+            Ok(Some(file_tailer)) => {
+                break file_tailer
+            }
+            Ok(None) => {
+                error!("No file found. Waiting for a file");
+            }
+            Err(e) => {
+                tokio::select! {
+                    reported = client::report(&tx, &config, e) => if reported.is_err() {
+                        return;
+                    },
+                    _ = shutdown.requested() => return,
                 }
             }
         }
-        
-        if abort_send_task {
-            tx_server_abort.send(()).await.unwrap();
+        tokio::select! {
+            _ = time::sleep(time::Duration::from_secs(2)) => {},
+            _ = shutdown.requested() => return,
         }
-        info!("client receive task stopped");
-    });
+    };
+
+    if let Err(e) = file_tailer.tail(tx, config.clone(), context, &mut switches).await {
+        error!("Stopped tailing {}: {}", config.get_application().name(), e);
+    }
+}
 
-    let file_tailer = FileTailer::new(config.get_log_file_name_regex(), config.get_log_file_dir()).await;
+// a server that is down must not hold back the others, so its copy of a message is dropped when its buffer is full
+async fn mirror(mut rx: mpsc::Receiver<crate::message::Message>, senders: Vec<mpsc::Sender<crate::message::Message>>, counters: Arc<Counters>) {
+    while let Some(msg) = rx.recv().await {
+        for sender in senders.iter() {
+            if let Err(e) = sender.try_send(msg.clone()) {
+                debug!("Dropping mirrored message: {}", e);
+                if telemetry::is_line(&msg) {
+                    counters.dropped(1);
+                }
+            }
+        }
+    }
+}
 
-    match file_tailer {
-        Some(mut file_tailer) => {
-            tokio::spawn(async move {
-                file_tailer.tail(tx, config).await;
-            });
+// connects to the servers in order, moving on to the next one whenever a connection fails or ends,
+// and backs off once every server has failed in a row
+async fn deliver(
+    config: &LogConfiguration,
+    servers: &[ServerEndpoint],
+    rx: &mut mpsc::Receiver<crate::message::Message>,
+    tailer: &TailerHandle,
+    context: &ClientContext
+) {
+    let statuses = context.get_statuses();
+    let mut shutdown = context.get_shutdown();
+    let application = config.get_application();
+    let mut backoff = Backoff::new(config.get_reconnect());
+    // kept across reconnects, a viewer who opens the application later still gets it
+    let mut backlog = Backlog::new(config.get_pause_buffer_lines(), config.get_pause_buffer_max_age(), Arc::clone(tailer.get_counters()));
+    let mut index = 0;
+    let mut failures = 0;
+    loop {
+        let server = &servers[index];
+        status::set_state(statuses, &application, &server.uri(), ConnectionState::Connecting).await;
+        if process_until_error(config, server, rx, &mut backlog, tailer, context).await {
+            backoff.reset();
+            failures = 0;
+        } else {
+            failures += 1;
         }
-        None => {
-            error!("No file found. Waiting for a file");
-            tokio::spawn(async move {
-                let mut file_tailer = loop {
-                    let file_tailer = FileTailer::new(config.get_log_file_name_regex(), config.get_log_file_dir()).await;
-                    match file_tailer {
-                        Some(file_tailer) => {
-                            break file_tailer
-                        }
-                        None => {
-                            time::sleep(time::Duration::from_secs(2)).await;
-                        }
-                    }
-                };
+        if context.is_shutting_down() {
+            return;
+        }
+        index = (index + 1) % servers.len();
 
-                file_tailer.tail(tx, config).await;
-            });
+        if failures >= servers.len() {
+            failures = 0;
+            status::set_state(statuses, &application, &server.uri(), ConnectionState::BackingOff).await;
+            let delay = backoff.next_delay();
+            info!("reconnecting {} in {:?} (attempt {})", application.name(), delay, backoff.attempt());
+            tokio::select! {
+                _ = time::sleep(delay) => {},
+                _ = shutdown.requested() => return,
+            }
+        } else if servers.len() > 1 {
+            status::set_state(statuses, &application, &server.uri(), ConnectionState::BackingOff).await;
+            info!("failing over {} to {}", application.name(), servers[index].uri());
         }
     }
-    // Send messages
-    let send_task = tokio::spawn(async move {
-        // Keep the connection alive
-        let mut send = false;
-        let mut abort_receive_task= true;
-        loop {
-            let msg = tokio::select! {
-                _ = rx_server_abort.recv() => {
-                    info!("client send task aborted");
-                    abort_receive_task = false;
-                    break;
-                },
-                msg = rx.recv() => {
-                    match msg {
-                        Some(msg) => msg,
-                        None => break,
-                    }
-                }
-            };
+}
+
+// returns whether the server started the application, so the caller knows when to reset the backoff
+async fn process_until_error(
+    config: &LogConfiguration,
+    server: &ServerEndpoint,
+    rx: &mut mpsc::Receiver<crate::message::Message>,
+    backlog: &mut Backlog,
+    tailer: &TailerHandle,
+    context: &ClientContext
+) -> bool {
+    let statuses = context.get_statuses();
+    let mut shutdown = context.get_shutdown();
+    let uri = server.uri();
+    let application = config.get_application();
+
+    let mut link = match context.get_connections().attach(config, server, context.get_shutdown().for_client()).await {
+        Ok(link) => link,
+        Err(e) => {
+            error!("Error connecting to {}: {}", uri, e);
+            return false;
+        }
+    };
+    status::set_state(statuses, &application, &uri, ConnectionState::Connected).await;
 
-            if msg.system().is_some() {
-                match msg.system().unwrap().message() {
-                    message::SystemMessages::Stop => {
-                        info!("stopped sending messages");
+    let counters = tailer.get_counters();
+    let stats_interval = config.get_stats_interval();
+    let mut stats = time::interval_at(time::Instant::now() + stats_interval, stats_interval);
+    let mut rates = Rates::new(counters);
+
+    // file lines stay in the channel until the server has started us, so nothing is lost after a reconnect
+    let mut started = false;
+    let mut send = false;
+    let mut resumed = false;
+    loop {
+        // control messages from the server come first so they aren't queued behind file lines
+        let msg = tokio::select! {
+            biased;
+            msg = link.recv() => {
+                match msg {
+                    Some(msg) => msg,
+                    None => {
+                        info!("connection to {} lost", uri);
                         break;
-                    }, 
-                    message::SystemMessages::Start => {
-                        info!("starting to send messages");
-                        send = true;
-                    },
-                    message::SystemMessages::Pause => {
-                        info!("paused sending messages");
-                        send = false;
+                    }
+                }
+            },
+            // a server that never started us has nothing to flush
+            _ = shutdown.requested(), if !started => break,
+            // sent whether paused or not, so the server always knows how the tailer is doing
+            _ = stats.tick(), if started => {
+                let reconnects = status::reconnects(statuses, &application).await;
+                let stats = rates.stats(counters, tailer.position(), reconnects).await;
+                let msg = message::Message::System(SystemMessage::new(application.clone(), message::SystemMessages::Stats(stats)));
+                if let Err(e) = link.send(msg).await {
+                    error!("Error sending stats: {}", e);
+                    break;
+                }
+                continue;
+            },
+            msg = rx.recv(), if started => {
+                match msg {
+                    // no one is watching, the line waits in the backlog for a viewer
+                    Some(msg) if !send => {
+                        backlog.push(msg);
+                        continue;
                     },
-                    message::SystemMessages::Resume => {
-                        info!("resumed sending messages");
-                        send = true;
+                    Some(msg) => msg,
+                    None => {
+                        // the tailer stopped and everything it read was sent
+                        link.stop().await;
+                        break;
                     },
-                    _ => {}
                 }
             }
-            let msg = serde_json::to_string(&msg).unwrap();
+        };
 
-            if send {
-                if let Err(e) = write.send(Message::Text(msg)).await {
+        // answered on the side and straight to the server whether paused or not, so new lines keep flowing
+        if let Some(system) = msg.system() {
+            if requests::answer(system.message(), config, tailer, link.sender()).await {
+                // the backfill covers what the backlog holds
+                if matches!(system.message(), message::SystemMessages::Backfill { .. }) {
+                    backlog.clear();
+                }
+                continue;
+            }
+            match system.message() {
+                message::SystemMessages::Stop => {
+                    info!("stopped sending messages");
+                    break;
+                }, 
+                message::SystemMessages::Start => {
+                    info!("starting to send messages");
+                    started = true;
+                    send = true;
+                    link.started();
+                    status::set_state(statuses, &application, &uri, ConnectionState::Connected).await;
+                },
+                message::SystemMessages::Pause => {
+                    info!("paused sending messages");
+                    send = false;
+                    status::set_state(statuses, &application, &uri, ConnectionState::Paused).await;
+                },
+                message::SystemMessages::Resume => {
+                    info!("resumed sending messages, {} buffered", backlog.len());
+                    send = true;
+                    status::set_state(statuses, &application, &uri, ConnectionState::Connected).await;
+                    resumed = true;
+                },
+                // the server refused to register the application
+                message::SystemMessages::Error(reason) if !started => {
+                    error!("{} rejected {}: {}", uri, application.name(), reason);
+                    break;
+                },
+                _ => {}
+            }
+        }
+        if send {
+            let is_line = telemetry::is_line(&msg);
+            if let Err(e) = link.send(msg).await {
+                error!("Error sending message: {}", e);
+                break;
+            }
+            if is_line {
+                counters.sent();
+            }
+        }
+        // what was read while paused goes before anything read after the resume
+        if std::mem::take(&mut resumed) && !backlog.is_empty() {
+            let mut failed = false;
+            for msg in backlog.drain() {
+                let is_line = telemetry::is_line(&msg);
+                if let Err(e) = link.send(msg).await {
                     error!("Error sending message: {}", e);
+                    failed = true;
                     break;
                 }
+                if is_line {
+                    counters.sent();
+                }
+            }
+            if failed {
+                break;
             }
         }
+    }
 
-        if abort_receive_task {
-            tx_client_abort.send(()).await.unwrap();
+    info!("client send task stopped");
+    started
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Write, sync::Arc};
+
+    use serde_json::json;
+
+    use crate::{client::{checkpoint, configuration::DEFAULT_SHUTDOWN_GRACE_PERIOD, connection, shutdown::Shutdown}, message::{DataMessage, SystemMessage, SystemMessages}, Applicatiton};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn shutdown_without_a_server_saves_the_checkpoint() {
+        let dir = env::temp_dir().join(format!("fefs-shutdown-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log_file = dir.join("app.log").display().to_string();
+        let checkpoint_dir = dir.join("checkpoints").display().to_string();
+        fs::write(&log_file, "").unwrap();
+
+        let config: LogConfiguration = serde_json::from_value(json!({
+            "app_name": { "SinglePod": "shutdown-test" },
+            "log_file_dir": dir,
+            "log_file_name_regex": "^app\\.log$",
+            "servers": [{ "host": "127.0.0.1", "port": 1, "path": "ws" }],
+            "channel_buffer": 1
+        })).unwrap();
+        let (shutdown_tx, shutdown) = Shutdown::new();
+        let context = ClientContext::new(Arc::new(status::new_statuses()), Arc::new(telemetry::new_tailers()), shutdown, Some(checkpoint_dir.clone()));
+        let handle = tokio::spawn(file(config, context));
+
+        // with nothing reading the channel the tailer ends up waiting for room in it
+        time::sleep(time::Duration::from_millis(300)).await;
+        let mut writer = fs::OpenOptions::new().append(true).open(&log_file).unwrap();
+        for line in 0..100 {
+            writeln!(writer, "line {}", line).unwrap();
         }
-        info!("client send task stopped");
-    });
+        time::sleep(time::Duration::from_millis(300)).await;
 
-    let _ = tokio::join!(receive_task, send_task);
-    info!("client stopped");
-}
+        let _ = shutdown_tx.send(true);
+        let stopped = time::timeout(DEFAULT_SHUTDOWN_GRACE_PERIOD, handle).await;
+        let checkpoint = checkpoint::load(&checkpoint_dir, &Applicatiton::SinglePod("shutdown-test".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
 
-async fn process_message(message: Result<Message, Error>, tx_clone: &Sender<crate::message::Message>) -> bool {
-    match message {
-        Ok(msg) => {
-            match msg {
-                Message::Text(text) => {
-                    info!("Received: {}", text);
-                    let message: crate::message::Message = serde_json::from_str(&text).unwrap();
-                    tx_clone.send(message).await.unwrap();
-                },
-                Message::Binary(data) => info!("Received binary data: {:?}", data),
-                Message::Ping(_) => debug!("Received ping"),
-                Message::Pong(_) => debug!("Received pong"),
-                Message::Close(_) => {
-                    info!("Server closed connection");
-                    return false
-                },
-                Message::Frame(_) => warn!("Received raw frame"),
-            }
+        assert!(matches!(stopped, Ok(Ok(()))), "the client did not stop within the grace period");
+        let checkpoint = checkpoint.unwrap();
+        assert_eq!(checkpoint.get_path(), log_file);
+        assert!(checkpoint.get_position() > 0);
+    }
+
+    async fn next(received: &mut mpsc::UnboundedReceiver<Option<message::Message>>) -> message::Message {
+        time::timeout(time::Duration::from_secs(5), received.recv()).await.unwrap().unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn lines_read_while_paused_are_sent_first_on_resume() {
+        let (server, mut received, send) = connection::tests::server().await;
+        let application = Applicatiton::SinglePod("paused-test".to_string());
+        let config: LogConfiguration = serde_json::from_value(json!({
+            "app_name": { "SinglePod": "paused-test" },
+            "log_file_dir": ".",
+            "log_file_name_regex": ".*",
+            "servers": [server],
+            "channel_buffer": 10,
+            "pause_buffer_lines": 3
+        })).unwrap();
+        let (_shutdown_tx, shutdown) = Shutdown::new();
+        let context = ClientContext::new(Arc::new(status::new_statuses()), Arc::new(telemetry::new_tailers()), shutdown, None);
+        let (tx, mut rx) = mpsc::channel(10);
+        tokio::spawn(async move {
+            let mut backlog = Backlog::new(config.get_pause_buffer_lines(), config.get_pause_buffer_max_age(), Arc::default());
+            process_until_error(&config, &server, &mut rx, &mut backlog, &TailerHandle::new().0, &context).await;
+        });
+
+        let system = |message: SystemMessages| message::Message::System(SystemMessage::new(application.clone(), message));
+        let line = |row: usize| message::Message::Data(DataMessage::new(row.to_string(), application.clone(), false));
+
+        assert_eq!(next(&mut received).await.system().unwrap().message(), &SystemMessages::Register);
+        send.send(system(SystemMessages::Start)).unwrap();
+        assert_eq!(next(&mut received).await.system().unwrap().message(), &SystemMessages::Start);
+        send.send(system(SystemMessages::Pause)).unwrap();
+        time::sleep(time::Duration::from_millis(200)).await;
+
+        // more than the backlog holds, the oldest are evicted
+        for row in 0..5 {
+            tx.send(line(row)).await.unwrap();
         }
-        Err(e) => {
-            error!("Error receiving message: {}", e);
-            return false
+        time::sleep(time::Duration::from_millis(200)).await;
+        send.send(system(SystemMessages::Resume)).unwrap();
+        assert_eq!(next(&mut received).await.system().unwrap().message(), &SystemMessages::Resume);
+        tx.send(line(5)).await.unwrap();
+
+        let mut rows = Vec::new();
+        for _ in 0..4 {
+            rows.push(next(&mut received).await.data().unwrap().row().to_string());
         }
+        assert_eq!(rows, ["2", "3", "4", "5"]);
     }
-
-    true
-}
\ No newline at end of file
+}
@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use tokio::sync::{mpsc, watch};
+
+use crate::{message::{FileInfo, Message, Replies, ReplyMessage, SystemMessages}, Applicatiton};
+
+use super::{checkpoint::Checkpoint, compressed::{FileCompression, LineReader}, configuration::LogConfiguration, error::ClientError, history, search::{self, Query}, telemetry::Counters, FileSearch};
+
+const SWITCH_BUFFER: usize = 4;
+
+// answers one request of the server, straight to the connection it came from whether paused or not
+#[derive(Clone)]
+pub struct Replier {
+    application: Applicatiton,
+    request_id: u64,
+    tx: mpsc::Sender<Message>
+}
+
+impl Replier {
+    pub fn new(application: Applicatiton, request_id: u64, tx: mpsc::Sender<Message>) -> Self {
+        Self { application, request_id, tx }
+    }
+
+    // false once the connection is gone
+    pub async fn send(&self, reply: Replies) -> bool {
+        let reply = ReplyMessage::new(self.application.clone(), self.request_id, reply);
+        self.tx.send(Message::Reply(reply)).await.is_ok()
+    }
+
+    pub async fn fail(&self, e: ClientError) {
+        error!("Cannot answer request {} of {}: {}", self.request_id, self.application.name(), e);
+        self.send(Replies::Error(e.to_string())).await;
+    }
+}
+
+// the server asked the tailer to move to another file
+pub struct Switch {
+    path: String,
+    replier: Replier
+}
+
+impl Switch {
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_replier(&self) -> &Replier {
+        &self.replier
+    }
+}
+
+// what requests of the server and the stats need from the tailer of an application
+#[derive(Clone)]
+pub struct TailerHandle {
+    progress: watch::Receiver<Option<Checkpoint>>,
+    switches: mpsc::Sender<Switch>,
+    counters: Arc<Counters>
+}
+
+impl TailerHandle {
+    // the handle and the ends the tailer keeps, where it publishes its position and takes switches from
+    pub fn new() -> (Self, watch::Sender<Option<Checkpoint>>, mpsc::Receiver<Switch>) {
+        let (progress_tx, progress) = watch::channel(None);
+        let (switches, switches_rx) = mpsc::channel(SWITCH_BUFFER);
+        (Self { progress, switches, counters: Arc::new(Counters::default()) }, progress_tx, switches_rx)
+    }
+
+    pub fn get_counters(&self) -> &Arc<Counters> {
+        &self.counters
+    }
+
+    pub fn position(&self) -> Option<Checkpoint> {
+        self.progress.borrow().clone()
+    }
+}
+
+// starts answering a request of the server, false when the message isn't one
+pub async fn answer(message: &SystemMessages, config: &LogConfiguration, tailer: &TailerHandle, tx: mpsc::Sender<Message>) -> bool {
+    let application = config.get_application();
+    match message {
+        SystemMessages::Backfill { request_id, lines, since } => {
+            info!("backfill {} requested", request_id);
+            let replier = Replier::new(application, *request_id, tx);
+            tokio::spawn(history::backfill(FileSearch::new(config), tailer.position(), *lines, *since, replier));
+        }
+        SystemMessages::ListFiles { request_id, count_lines } => {
+            info!("file list {} requested", request_id);
+            let replier = Replier::new(application, *request_id, tx);
+            tokio::spawn(list_files(FileSearch::new(config), tailer.position(), *count_lines, replier));
+        }
+        SystemMessages::SwitchFile { request_id, path } => {
+            info!("switch {} to {} requested", request_id, path);
+            let replier = Replier::new(application, *request_id, tx);
+            let switch = Switch { path: path.clone(), replier: replier.clone() };
+            if tailer.switches.send(switch).await.is_err() {
+                replier.fail(ClientError::NotTailing).await;
+            }
+        }
+        SystemMessages::StreamFile { request_id, path } => {
+            info!("file {} requested by {}", path, request_id);
+            let replier = Replier::new(application, *request_id, tx);
+            tokio::spawn(stream_file(FileSearch::new(config), path.clone(), replier));
+        }
+        SystemMessages::Search { request_id, pattern, from, to, limit } => {
+            info!("search {} for {} requested", request_id, pattern);
+            let replier = Replier::new(application, *request_id, tx);
+            let query = Query::new(pattern.clone(), *from, *to, *limit);
+            tokio::spawn(search::search(FileSearch::new(config), query, replier));
+        }
+        _ => return false,
+    }
+    true
+}
+
+async fn list_files(search: FileSearch, position: Option<Checkpoint>, count_lines: bool, replier: Replier) {
+    let candidates = match search.candidates(None) {
+        Ok(candidates) => candidates,
+        Err(e) => return replier.fail(e).await,
+    };
+    let tailed = position.map(|position| position.get_path().to_string());
+    let mut files = vec![];
+    for candidate in candidates {
+        let modified = DateTime::<Utc>::from(candidate.modified()).naive_utc();
+        let found = candidate.into_file();
+        let is_tailed = tailed.as_deref() == Some(found.path.as_str());
+        let compressed = FileCompression::of(&found.path).is_some();
+        let file = FileInfo::new(found.path, found.size, modified, is_tailed, compressed);
+        if !count_lines {
+            files.push(file);
+            continue;
+        }
+        match count(file.path()).await {
+            Ok(lines) => files.push(file.with_lines(lines)),
+            Err(e) => return replier.fail(e).await,
+        }
+    }
+    files.sort_by(|a, b| a.path().cmp(b.path()));
+    replier.send(Replies::Files(files)).await;
+}
+
+async fn count(path: &str) -> Result<u64, ClientError> {
+    let mut reader = LineReader::open(path).await?;
+    let mut lines = 0;
+    while reader.next_line().await?.is_some() {
+        lines += 1;
+    }
+    Ok(lines)
+}
+
+// only files the client would tail can be read, whatever path the server sends, compressed ones are
+// sent decompressed
+async fn stream_file(search: FileSearch, path: String, replier: Replier) {
+    let found = match search.candidate(&path) {
+        Ok(found) => found.into_file(),
+        Err(e) => return replier.fail(e).await,
+    };
+    let mut reader = match LineReader::open(&found.path).await {
+        Ok(reader) => reader,
+        Err(e) => return replier.fail(e).await,
+    };
+    loop {
+        match reader.next_line().await {
+            Ok(Some(line)) => {
+                if !replier.send(Replies::History(line)).await {
+                    return;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => return replier.fail(e).await,
+        }
+    }
+    replier.send(Replies::Done).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    fn config(dir: &std::path::Path) -> LogConfiguration {
+        LogConfiguration::new(Applicatiton::SinglePod("app".to_string()), dir.display().to_string(), "^app.*\\.log$".to_string(), vec![])
+    }
+
+    async fn replies(rx: &mut mpsc::Receiver<Message>) -> Vec<Replies> {
+        let mut replies = vec![];
+        while let Some(Message::Reply(reply)) = rx.recv().await {
+            replies.push(reply.reply().clone());
+        }
+        replies
+    }
+
+    #[tokio::test]
+    async fn matching_files_are_listed_with_the_tailed_one() {
+        let dir = env::temp_dir().join(format!("fefs-list-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("app-2.log"), "two\nlines\n").unwrap();
+        fs::write(dir.join("app-1.log"), "one\n").unwrap();
+        fs::write(dir.join("other.txt"), "").unwrap();
+
+        let tailed = dir.join("app-2.log").display().to_string();
+        let (tx, mut rx) = mpsc::channel(10);
+        let replier = Replier::new(Applicatiton::SinglePod("app".to_string()), 1, tx);
+        list_files(FileSearch::new(&config(&dir)), Some(Checkpoint::new(tailed.clone(), None, 0)), true, replier).await;
+        let listed = replies(&mut rx).await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        let files = match &listed[..] {
+            [Replies::Files(files)] => serde_json::to_value(files).unwrap(),
+            listed => panic!("expected a file list, got {:?}", listed),
+        };
+        let files = files.as_array().unwrap();
+        let paths: Vec<_> = files.iter().map(|file| file["path"].as_str().unwrap().to_string()).collect();
+        assert_eq!(paths, [dir.join("app-1.log").display().to_string(), tailed]);
+        assert_eq!(files.iter().map(|file| file["tailed"].clone()).collect::<Vec<_>>(), [false, true]);
+        assert_eq!(files.iter().map(|file| file["lines"].clone()).collect::<Vec<_>>(), [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn only_files_the_client_would_tail_are_streamed() {
+        let dir = env::temp_dir().join(format!("fefs-stream-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("app.log"), "one\r\ntwo\n").unwrap();
+        fs::write(dir.join("secret.txt"), "hidden\n").unwrap();
+        let application = Applicatiton::SinglePod("app".to_string());
+
+        let (tx, mut rx) = mpsc::channel(10);
+        stream_file(FileSearch::new(&config(&dir)), dir.join("app.log").display().to_string(), Replier::new(application.clone(), 1, tx)).await;
+        let streamed = replies(&mut rx).await;
+
+        let (tx, mut rx) = mpsc::channel(10);
+        stream_file(FileSearch::new(&config(&dir)), dir.join("secret.txt").display().to_string(), Replier::new(application, 2, tx)).await;
+        let refused = replies(&mut rx).await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(streamed, [Replies::History("one".to_string()), Replies::History("two".to_string()), Replies::Done]);
+        assert!(matches!(refused[..], [Replies::Error(_)]));
+    }
+}
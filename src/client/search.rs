@@ -0,0 +1,154 @@
+use chrono::NaiveDateTime;
+use log::info;
+use regex::Regex;
+use crate::message::Replies;
+
+use super::{compressed::LineReader, error::ClientError, history, requests::Replier, FileSearch};
+
+// a search never sends more matches than this, whatever was asked for
+pub const MAX_SEARCH_MATCHES: usize = 10_000;
+
+pub struct Query {
+    pattern: String,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+    limit: Option<usize>
+}
+
+impl Query {
+    pub fn new(pattern: String, from: Option<NaiveDateTime>, to: Option<NaiveDateTime>, limit: Option<usize>) -> Self {
+        Self { pattern, from, to, limit }
+    }
+}
+
+// greps every file the client could tail, compressed ones included, oldest first, then sends Done
+pub(super) async fn search(files: FileSearch, query: Query, replier: Replier) {
+    match search_files(&files, &query, &replier).await {
+        Ok(Some(found)) => {
+            info!("Search found {} lines", found);
+            replier.send(Replies::Done).await;
+        }
+        // no one is waiting for the rest
+        Ok(None) => {}
+        Err(e) => replier.fail(e).await,
+    }
+}
+
+// how many lines were found, None once the replies can't be sent
+async fn search_files(files: &FileSearch, query: &Query, replier: &Replier) -> Result<Option<usize>, ClientError> {
+    let regex = Regex::new(&query.pattern).map_err(ClientError::SearchPattern)?;
+    let limit = query.limit.unwrap_or(MAX_SEARCH_MATCHES).min(MAX_SEARCH_MATCHES);
+
+    let mut candidates = files.candidates(None)?;
+    candidates.sort_by_key(|candidate| candidate.modified());
+    let mut found = 0;
+    for candidate in candidates {
+        if found >= limit {
+            break;
+        }
+        let path = candidate.into_file().path;
+        match search_file(&path, &regex, query, limit - found, replier).await? {
+            Some(in_file) => found += in_file,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(found))
+}
+
+// lines without a timestamp, like the rest of a stack trace, belong to the last line that had one,
+// with a time range lines before the first timestamp are left out. timestamps are compared as they
+// are written, often in local time, so modification times can't rule out a file
+async fn search_file(path: &str, regex: &Regex, query: &Query, limit: usize, replier: &Replier) -> Result<Option<usize>, ClientError> {
+    let mut reader = LineReader::open(path).await?;
+    let ranged = query.from.is_some() || query.to.is_some();
+    let mut last_timestamp = None;
+    let mut line_number = 0;
+    let mut found = 0;
+    while found < limit {
+        let line = match reader.next_line().await? {
+            Some(line) => line,
+            None => break,
+        };
+        line_number += 1;
+        if let Some(timestamp) = history::timestamp(&line) {
+            last_timestamp = Some(timestamp);
+        }
+        if ranged {
+            match last_timestamp {
+                Some(timestamp) if query.to.is_some_and(|to| timestamp > to) => break,
+                Some(timestamp) if query.from.is_some_and(|from| timestamp < from) => continue,
+                Some(_) => {}
+                None => continue,
+            }
+        }
+        if !regex.is_match(&line) {
+            continue;
+        }
+        found += 1;
+        let reply = Replies::Match { path: path.to_string(), line_number, line };
+        if !replier.send(reply).await {
+            return Ok(None);
+        }
+    }
+    Ok(Some(found))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path};
+
+    use chrono::NaiveDate;
+    use tokio::sync::mpsc;
+
+    use crate::{client::configuration::LogConfiguration, message::Message, Applicatiton};
+
+    use super::*;
+
+    fn at(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, 19).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    async fn run(dir: &Path, query: Query) -> Vec<Replies> {
+        let config = LogConfiguration::new(Applicatiton::SinglePod("app".to_string()), dir.display().to_string(), "^app\\.log$".to_string(), vec![]);
+        let (tx, mut rx) = mpsc::channel(100);
+        search(FileSearch::new(&config), query, Replier::new(Applicatiton::SinglePod("app".to_string()), 1, tx)).await;
+        let mut replies = vec![];
+        while let Some(Message::Reply(reply)) = rx.recv().await {
+            replies.push(reply.reply().clone());
+        }
+        replies
+    }
+
+    fn matches(replies: &[Replies]) -> Vec<(u64, &str)> {
+        replies.iter()
+            .filter_map(|reply| match reply {
+                Replies::Match { line_number, line, .. } => Some((*line_number, line.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn matching_lines_in_a_time_range() {
+        let dir = env::temp_dir().join(format!("fefs-search-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("app.log"), "error before any timestamp\n\
+            2026-10-19T10:00:00 error early\n\
+            2026-10-19T12:00:00 error in range\n\
+            \tat error in a stack frame\n\
+            2026-10-19T12:30:00 fine\n\
+            2026-10-19T14:00:00 error late\n").unwrap();
+
+        let everything = run(&dir, Query::new("error".to_string(), None, None, None)).await;
+        let ranged = run(&dir, Query::new("error".to_string(), Some(at(11)), Some(at(13)), None)).await;
+        let limited = run(&dir, Query::new("error".to_string(), None, None, Some(2))).await;
+        let invalid = run(&dir, Query::new("(".to_string(), None, None, None)).await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(matches(&everything).len(), 5);
+        assert_eq!(everything.last(), Some(&Replies::Done));
+        assert_eq!(matches(&ranged), [(3, "2026-10-19T12:00:00 error in range"), (4, "\tat error in a stack frame")]);
+        assert_eq!(matches(&limited), [(1, "error before any timestamp"), (2, "2026-10-19T10:00:00 error early")]);
+        assert!(matches!(invalid[..], [Replies::Error(_)]));
+    }
+}
@@ -0,0 +1,146 @@
+use std::{cmp::Ordering, time::{Duration, SystemTime}};
+
+use serde::{Deserialize, Serialize};
+
+// which of the files matching log_file_name_regex is the one being written to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileSelection {
+    // the most recently modified file
+    #[default]
+    NewestModified,
+    // the greatest name, for names that sort by date like app-2026-10-18.log
+    LexicographicGreatest,
+    // the greatest name comparing numbers by value, so app-10.log comes after app-9.log
+    Natural
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate<T> {
+    name: String,
+    modified: SystemTime,
+    file: T
+}
+
+impl<T> Candidate<T> {
+    pub fn new(name: String, modified: SystemTime, file: T) -> Self {
+        Self { name, modified, file }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn modified(&self) -> SystemTime {
+        self.modified
+    }
+
+    pub fn file(&self) -> &T {
+        &self.file
+    }
+
+    pub fn into_file(self) -> T {
+        self.file
+    }
+}
+
+impl FileSelection {
+    // files last modified longer than max_age ago are never picked, ties go to the greatest name
+    pub fn select<T>(&self, candidates: Vec<Candidate<T>>, max_age: Option<Duration>) -> Option<Candidate<T>> {
+        let now = SystemTime::now();
+        candidates.into_iter()
+            .filter(|candidate| match max_age {
+                // a modification time in the future counts as new
+                Some(max_age) => now.duration_since(candidate.modified).map(|age| age <= max_age).unwrap_or(true),
+                None => true,
+            })
+            .max_by(|a, b| self.compare(a, b))
+    }
+
+    fn compare<T>(&self, a: &Candidate<T>, b: &Candidate<T>) -> Ordering {
+        match self {
+            FileSelection::NewestModified => a.modified.cmp(&b.modified).then_with(|| a.name.cmp(&b.name)),
+            FileSelection::LexicographicGreatest => a.name.cmp(&b.name),
+            FileSelection::Natural => natural_cmp(&a.name, &b.name),
+        }
+    }
+}
+
+// compares runs of digits by their value and everything else character by character
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x_digits, x_rest) = split_digits(a);
+                let (y_digits, y_rest) = split_digits(b);
+                let x_value = x_digits.trim_start_matches('0');
+                let y_value = y_digits.trim_start_matches('0');
+                let ordering = x_value.len().cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x_digits.len().cmp(&y_digits.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a = x_rest;
+                b = y_rest;
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a = &a[x.len_utf8()..];
+                b = &b[y.len_utf8()..];
+            }
+        }
+    }
+}
+
+fn split_digits(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(files: &[(&str, u64)]) -> Vec<Candidate<()>> {
+        let now = SystemTime::now();
+        files.iter()
+            .map(|(name, age)| Candidate::new(name.to_string(), now - Duration::from_secs(*age), ()))
+            .collect()
+    }
+
+    fn selected(selection: FileSelection, files: &[(&str, u64)], max_age: Option<Duration>) -> Option<String> {
+        selection.select(candidates(files), max_age).map(|candidate| candidate.name().to_string())
+    }
+
+    #[test]
+    fn natural_order() {
+        assert_eq!(natural_cmp("app-9.log", "app-10.log"), Ordering::Less);
+        assert_eq!(natural_cmp("app-10.log", "app-10.log"), Ordering::Equal);
+        assert_eq!(natural_cmp("app-010.log", "app-10.log"), Ordering::Greater);
+        assert_eq!(natural_cmp("app-2.log", "app-2.log.1"), Ordering::Less);
+        assert_eq!(natural_cmp("app-2.log", "app-b.log"), Ordering::Less);
+        assert_eq!(natural_cmp("app-99999999999999999999999.log", "app-100000000000000000000000.log"), Ordering::Less);
+    }
+
+    #[test]
+    fn strategies() {
+        let files = [("app-9.log", 10), ("app-10.log", 20), ("app-1.log", 5)];
+        assert_eq!(selected(FileSelection::NewestModified, &files, None).as_deref(), Some("app-1.log"));
+        assert_eq!(selected(FileSelection::LexicographicGreatest, &files, None).as_deref(), Some("app-9.log"));
+        assert_eq!(selected(FileSelection::Natural, &files, None).as_deref(), Some("app-10.log"));
+    }
+
+    #[test]
+    fn max_age() {
+        let files = [("app-9.log", 10), ("app-10.log", 120)];
+        assert_eq!(selected(FileSelection::Natural, &files, Some(Duration::from_secs(60))).as_deref(), Some("app-9.log"));
+        assert_eq!(selected(FileSelection::Natural, &files, Some(Duration::from_secs(1))), None);
+        assert_eq!(selected(FileSelection::Natural, &[], None), None);
+    }
+}
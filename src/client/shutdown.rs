@@ -0,0 +1,76 @@
+use log::{error, info};
+use tokio::sync::watch;
+
+// a cheap handle every tailer and connection keeps to find out the client is stopping
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+    // set for a single tailer, so the supervisor can stop it without stopping the client
+    stop: Option<watch::Receiver<bool>>
+}
+
+impl Shutdown {
+    pub fn new() -> (watch::Sender<bool>, Self) {
+        let (tx, rx) = watch::channel(false);
+        (tx, Self { rx, stop: None })
+    }
+
+    // a handle that is requested on shutdown or once the returned sender stops it on its own
+    pub fn with_stop(&self) -> (watch::Sender<bool>, Self) {
+        let (tx, stop) = watch::channel(false);
+        (tx, Self { rx: self.rx.clone(), stop: Some(stop) })
+    }
+
+    // what the whole client shares, for anything that outlives a single tailer
+    pub fn for_client(&self) -> Self {
+        Self { rx: self.rx.clone(), stop: None }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.rx.borrow() || self.stop.as_ref().is_some_and(|stop| *stop.borrow())
+    }
+
+    // resolves once shutdown was requested, or never if the sender is gone without requesting it
+    pub async fn requested(&mut self) {
+        match self.stop.as_mut() {
+            Some(stop) => tokio::select! {
+                _ = requested(&mut self.rx) => {},
+                _ = requested(stop) => {},
+            },
+            None => requested(&mut self.rx).await,
+        }
+    }
+}
+
+async fn requested(rx: &mut watch::Receiver<bool>) {
+    if rx.wait_for(|requested| *requested).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+// waits for SIGTERM or SIGINT (ctrl-c)
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                error!("Cannot listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
+        tokio::select! {
+            _ = terminate.recv() => info!("received SIGTERM"),
+            _ = tokio::signal::ctrl_c() => info!("received SIGINT"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        info!("received ctrl-c");
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::BTreeMap, sync::Arc, thread, time::Duration};
+
+use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
+use chrono::NaiveDateTime;
+use log::{error, info};
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::Applicatiton;
+
+use super::{metrics, telemetry::Tailers};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Paused,
+    BackingOff
+}
+
+impl ConnectionState {
+    // paused connections are up, the server just has no viewer
+    pub fn is_up(&self) -> bool {
+        matches!(self, ConnectionState::Connected | ConnectionState::Paused)
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ApplicationStatus {
+    application: Applicatiton,
+    endpoint: String,
+    state: ConnectionState,
+    since: NaiveDateTime,
+    // since when the connection has been down, whatever it went through since
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disconnected_since: Option<NaiveDateTime>,
+    reconnects: u64
+}
+
+impl ApplicationStatus {
+    fn new(application: Applicatiton, endpoint: String, state: ConnectionState) -> Self {
+        let since = chrono::Utc::now().naive_utc();
+        let disconnected_since = if state.is_up() { None } else { Some(since) };
+        Self { application, endpoint, state, since, disconnected_since, reconnects: 0 }
+    }
+
+    pub fn application(&self) -> &Applicatiton {
+        &self.application
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    pub fn since(&self) -> NaiveDateTime {
+        self.since
+    }
+
+    pub fn disconnected_since(&self) -> Option<NaiveDateTime> {
+        self.disconnected_since
+    }
+
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+}
+
+// keyed by application and server endpoint, an application has one connection per endpoint it uses
+pub type Statuses = Mutex<BTreeMap<(Applicatiton, String), ApplicationStatus>>;
+
+pub fn new_statuses() -> Statuses {
+    Mutex::new(BTreeMap::new())
+}
+
+pub async fn set_state(statuses: &Statuses, application: &Applicatiton, endpoint: &str, state: ConnectionState) {
+    let mut locked_statuses = statuses.lock().await;
+    let status = locked_statuses
+        .entry((application.clone(), endpoint.to_string()))
+        .or_insert_with(|| ApplicationStatus::new(application.clone(), endpoint.to_string(), state));
+
+    if status.state != state {
+        info!("connection state of {} to {} changed from {:?} to {:?}", application.name(), endpoint, status.state, state);
+        // every time we go back to connecting after the first attempt counts as a reconnect
+        if state == ConnectionState::Connecting {
+            status.reconnects += 1;
+        }
+        status.since = chrono::Utc::now().naive_utc();
+        if state.is_up() {
+            status.disconnected_since = None;
+        } else if status.state.is_up() {
+            status.disconnected_since = Some(status.since);
+        }
+        status.state = state;
+    }
+}
+
+// over every server the application connects to
+pub async fn reconnects(statuses: &Statuses, application: &Applicatiton) -> u64 {
+    statuses.lock().await.values()
+        .filter(|status| status.application() == application)
+        .map(ApplicationStatus::reconnects)
+        .sum()
+}
+
+pub async fn remove_application(statuses: &Statuses, application: &Applicatiton) {
+    statuses.lock().await.retain(|(status_application, _), _| status_application != application);
+}
+
+// the applications none of whose connections is up, with since when, an application failing over
+// is down from when its last connection went down
+pub async fn disconnected(statuses: &Statuses) -> BTreeMap<Applicatiton, NaiveDateTime> {
+    let mut disconnected: BTreeMap<Applicatiton, Option<NaiveDateTime>> = BTreeMap::new();
+    for status in statuses.lock().await.values() {
+        let since = disconnected.entry(status.application.clone()).or_insert(status.disconnected_since);
+        *since = since.zip(status.disconnected_since).map(|(since, other)| since.max(other));
+    }
+    disconnected.into_iter()
+        .filter_map(|(application, since)| since.map(|since| (application, since)))
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+struct Disconnected {
+    application: Applicatiton,
+    since: NaiveDateTime
+}
+
+#[get("/status")]
+async fn get_status(statuses: web::Data<Arc<Statuses>>) -> impl Responder {
+    let statuses: Vec<ApplicationStatus> = statuses.lock().await.values().cloned().collect();
+    HttpResponse::Ok().json(statuses)
+}
+
+// fails with the applications that have been disconnected for longer than allowed
+#[get("/healthz")]
+async fn get_healthz(statuses: web::Data<Arc<Statuses>>, max_disconnected: web::Data<Duration>) -> impl Responder {
+    let now = chrono::Utc::now().naive_utc();
+    let unhealthy: Vec<Disconnected> = disconnected(&statuses).await.into_iter()
+        .filter(|(_, since)| (now - *since).to_std().unwrap_or_default() > **max_disconnected)
+        .map(|(application, since)| Disconnected { application, since })
+        .collect();
+    if unhealthy.is_empty() {
+        HttpResponse::Ok().body("ok")
+    } else {
+        HttpResponse::ServiceUnavailable().json(unhealthy)
+    }
+}
+
+// the client runs on a plain tokio runtime, so the status server gets its own actix system on a separate thread
+pub fn serve(address: String, statuses: Arc<Statuses>, tailers: Arc<Tailers>, max_disconnected: Duration) {
+    thread::spawn(move || {
+        info!("serving client status at http://{}/status, metrics at /metrics and health at /healthz", address);
+        let result = actix_web::rt::System::new().block_on(async move {
+            HttpServer::new(move || {
+                App::new()
+                    .app_data(web::Data::new(Arc::clone(&statuses)))
+                    .app_data(web::Data::new(Arc::clone(&tailers)))
+                    .app_data(web::Data::new(max_disconnected))
+                    .service(get_status)
+                    .service(get_healthz)
+                    .service(metrics::get_metrics)
+            })
+            .workers(1)
+            .bind(&address)?
+            .run()
+            .await
+        });
+
+        if let Err(e) = result {
+            error!("client status server stopped: {}", e);
+        }
+    });
+}
@@ -0,0 +1,323 @@
+use std::{collections::{BTreeMap, BTreeSet}, path::Path, time::Duration};
+
+use log::{error, info, warn};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::{sync::{mpsc, watch}, task::JoinHandle, time};
+
+use crate::Applicatiton;
+
+use super::{configuration::{ClientConfiguration, LogConfiguration}, context::ClientContext, process, status, telemetry};
+
+// editors write a file in several steps, so a reload waits for them to settle
+const RELOAD_DELAY: Duration = Duration::from_millis(200);
+
+struct Tailer {
+    config: LogConfiguration,
+    handle: JoinHandle<()>,
+    stop: watch::Sender<bool>
+}
+
+// runs one process::file per application and keeps them in line with the configuration file,
+// tailers whose configuration didn't change are left alone when the file is edited,
+// returns once every tailer has finished after a shutdown
+pub async fn supervise(path: String, client_configuration: ClientConfiguration, context: ClientContext) {
+    let status_address = client_configuration.get_status_address();
+    let max_disconnected = client_configuration.get_max_disconnected();
+    let checkpoint_dir = client_configuration.get_checkpoint_dir();
+    let shutdown_grace_period = client_configuration.get_shutdown_grace_period();
+    report_issues(&client_configuration);
+    let mut tailers: BTreeMap<Applicatiton, Tailer> = BTreeMap::new();
+    apply(client_configuration.get_configurations(), BTreeSet::new(), &mut tailers, shutdown_grace_period, &context).await;
+
+    let mut shutdown = context.get_shutdown();
+    let (changes_tx, mut changes) = mpsc::unbounded_channel();
+    // dropping the watcher stops it, so it's kept until the supervisor returns
+    let _watcher = match watch(&path, changes_tx) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            error!("Cannot watch {} for changes, it is only read at startup: {}", path, e);
+            None
+        }
+    };
+    loop {
+        tokio::select! {
+            // without a watcher the channel is closed and only a shutdown is left to wait for
+            Some(()) = changes.recv() => {},
+            _ = shutdown.requested() => break,
+        }
+        tokio::select! {
+            _ = time::sleep(RELOAD_DELAY) => {},
+            _ = shutdown.requested() => break,
+        }
+        while changes.try_recv().is_ok() {}
+
+        let client_configuration = match ClientConfiguration::load(&path) {
+            Ok(client_configuration) => client_configuration,
+            Err(e) => {
+                error!("Keeping the running configuration, reloading {} failed: {}", path, e);
+                continue;
+            }
+        };
+        info!("reloading configuration from {}", path);
+        report_issues(&client_configuration);
+        if client_configuration.get_status_address() != status_address || client_configuration.get_max_disconnected() != max_disconnected {
+            warn!("status_address and max_disconnected_secs changes only take effect after a restart");
+        }
+        if client_configuration.get_checkpoint_dir() != checkpoint_dir || client_configuration.get_shutdown_grace_period() != shutdown_grace_period {
+            warn!("checkpoint_dir and shutdown_grace_period_ms changes only take effect after a restart");
+        }
+        let invalid = invalid_applications(&client_configuration);
+        apply(client_configuration.get_configurations(), invalid, &mut tailers, shutdown_grace_period, &context).await;
+    }
+
+    info!("waiting for {} tailer(s) to stop", tailers.len());
+    for (application, tailer) in tailers {
+        if let Err(e) = tailer.handle.await {
+            error!("tailer for {} failed while stopping: {}", application.name(), e);
+        }
+    }
+}
+
+// the directory is watched rather than the file, editors and config map updates replace the file
+// instead of writing to it
+fn watch(path: &str, changes: mpsc::UnboundedSender<()>) -> notify::Result<RecommendedWatcher> {
+    let path = Path::new(path);
+    let file_name = path.file_name().map(|file_name| file_name.to_os_string());
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        match event {
+            Ok(event) => {
+                let changed = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_))
+                    && event.paths.iter().any(|path| path.file_name().map(|name| name.to_os_string()) == file_name);
+                if changed {
+                    let _ = changes.send(());
+                }
+            }
+            Err(e) => error!("Error watching the configuration file: {}", e),
+        }
+    })?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+// an application whose new configuration is invalid keeps tailing with the one it had
+async fn apply(
+    configs: Vec<LogConfiguration>,
+    invalid: BTreeSet<Applicatiton>,
+    tailers: &mut BTreeMap<Applicatiton, Tailer>,
+    grace_period: Duration,
+    context: &ClientContext
+) {
+    // validation already left out every configuration of an application but the first
+    let configs_by_application: BTreeMap<Applicatiton, LogConfiguration> = configs.into_iter()
+        .map(|config| (config.get_application(), config))
+        .collect();
+
+    let removed: Vec<Applicatiton> = tailers.keys()
+        .filter(|application| !configs_by_application.contains_key(application))
+        .cloned()
+        .collect();
+    for application in removed {
+        if invalid.contains(&application) {
+            warn!("keeping the running tailer for {}, its new configuration is invalid", application.name());
+            continue;
+        }
+        info!("stopping tailer for {}", application.name());
+        stop(tailers.remove(&application).unwrap(), &application, grace_period, context).await;
+    }
+
+    for (application, config) in configs_by_application {
+        match tailers.get(&application) {
+            Some(tailer) if tailer.config == config => continue,
+            Some(_) => {
+                info!("restarting tailer for {}", application.name());
+                stop(tailers.remove(&application).unwrap(), &application, grace_period, context).await;
+            }
+            None => info!("starting tailer for {}", application.name()),
+        }
+
+        let (stop, context) = context.with_stop();
+        let process_config = config.clone();
+        let handle = tokio::spawn(async move {
+            process::file(process_config, context).await;
+        });
+        tailers.insert(application, Tailer { config, handle, stop });
+    }
+}
+
+// stopped like on shutdown, so the checkpoint is saved and what was read is still delivered,
+// only a tailer that doesn't finish within the grace period is aborted
+async fn stop(mut tailer: Tailer, application: &Applicatiton, grace_period: Duration, context: &ClientContext) {
+    let _ = tailer.stop.send(true);
+    if time::timeout(grace_period, &mut tailer.handle).await.is_err() {
+        warn!("tailer for {} didn't stop within {:?}, aborting it", application.name(), grace_period);
+        tailer.handle.abort();
+        let _ = tailer.handle.await;
+    }
+    status::remove_application(context.get_statuses(), application).await;
+    telemetry::remove_tailer(context.get_tailers(), application).await;
+}
+
+fn report_issues(client_configuration: &ClientConfiguration) {
+    for issue in client_configuration.get_issues() {
+        error!("Skipping invalid configuration, {}", issue);
+    }
+}
+
+fn invalid_applications(client_configuration: &ClientConfiguration) -> BTreeSet<Applicatiton> {
+    client_configuration.get_issues().iter()
+        .filter_map(|issue| issue.application().cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Write, sync::Arc};
+
+    use serde_json::json;
+    use tokio::task::Id;
+
+    use crate::client::{checkpoint, configuration::DEFAULT_SHUTDOWN_GRACE_PERIOD, shutdown::Shutdown};
+
+    use super::*;
+
+    // no file matches and nothing listens on the port, so the tailer just keeps waiting
+    fn config(name: &str, regex: &str) -> LogConfiguration {
+        config_in(env!("CARGO_MANIFEST_DIR"), name, regex)
+    }
+
+    fn config_in(dir: &str, name: &str, regex: &str) -> LogConfiguration {
+        serde_json::from_value(json!({
+            "app_name": { "SinglePod": name },
+            "log_file_dir": dir,
+            "log_file_name_regex": regex,
+            "servers": [{ "host": "127.0.0.1", "port": 1, "path": "ws" }],
+            "channel_buffer": 10
+        })).unwrap()
+    }
+
+    fn application(name: &str) -> Applicatiton {
+        Applicatiton::SinglePod(name.to_string())
+    }
+
+    fn context() -> ClientContext {
+        let (_, shutdown) = Shutdown::new();
+        ClientContext::new(Arc::new(status::new_statuses()), Arc::new(telemetry::new_tailers()), shutdown, None)
+    }
+
+    fn ids(tailers: &BTreeMap<Applicatiton, Tailer>) -> BTreeMap<Applicatiton, Id> {
+        tailers.iter().map(|(application, tailer)| (application.clone(), tailer.handle.id())).collect()
+    }
+
+    #[tokio::test]
+    async fn added_applications_are_started() {
+        let context = context();
+        let mut tailers = BTreeMap::new();
+        apply(vec![config("a", "^a$")], BTreeSet::new(), &mut tailers, DEFAULT_SHUTDOWN_GRACE_PERIOD, &context).await;
+        let before = ids(&tailers);
+
+        apply(vec![config("a", "^a$"), config("b", "^b$")], BTreeSet::new(), &mut tailers, DEFAULT_SHUTDOWN_GRACE_PERIOD, &context).await;
+        assert_eq!(tailers.keys().cloned().collect::<Vec<_>>(), vec![application("a"), application("b")]);
+        assert_eq!(tailers[&application("a")].handle.id(), before[&application("a")]);
+        assert!(tailers.values().all(|tailer| !tailer.handle.is_finished()));
+    }
+
+    #[tokio::test]
+    async fn removed_applications_are_stopped() {
+        let context = context();
+        let mut tailers = BTreeMap::new();
+        apply(vec![config("a", "^a$"), config("b", "^b$")], BTreeSet::new(), &mut tailers, DEFAULT_SHUTDOWN_GRACE_PERIOD, &context).await;
+        let before = ids(&tailers);
+
+        apply(vec![config("a", "^a$")], BTreeSet::new(), &mut tailers, DEFAULT_SHUTDOWN_GRACE_PERIOD, &context).await;
+        assert_eq!(ids(&tailers), BTreeMap::from([(application("a"), before[&application("a")])]));
+        assert!(context.get_statuses().lock().await.keys().all(|(status_application, _)| *status_application != application("b")));
+    }
+
+    #[tokio::test]
+    async fn changed_applications_are_restarted() {
+        let context = context();
+        let mut tailers = BTreeMap::new();
+        apply(vec![config("a", "^a$"), config("b", "^b$")], BTreeSet::new(), &mut tailers, DEFAULT_SHUTDOWN_GRACE_PERIOD, &context).await;
+        let before = ids(&tailers);
+
+        apply(vec![config("a", "^a$"), config("b", "^b.log$")], BTreeSet::new(), &mut tailers, DEFAULT_SHUTDOWN_GRACE_PERIOD, &context).await;
+        let after = ids(&tailers);
+        assert_eq!(after[&application("a")], before[&application("a")]);
+        assert_ne!(after[&application("b")], before[&application("b")]);
+        assert_eq!(tailers[&application("b")].config, config("b", "^b.log$"));
+    }
+
+    #[tokio::test]
+    async fn invalid_applications_keep_their_tailer() {
+        let context = context();
+        let mut tailers = BTreeMap::new();
+        apply(vec![config("a", "^a$"), config("b", "^b$")], BTreeSet::new(), &mut tailers, DEFAULT_SHUTDOWN_GRACE_PERIOD, &context).await;
+        let before = ids(&tailers);
+
+        apply(vec![config("a", "^a$")], BTreeSet::from([application("b")]), &mut tailers, DEFAULT_SHUTDOWN_GRACE_PERIOD, &context).await;
+        assert_eq!(ids(&tailers), before);
+        assert_eq!(tailers[&application("b")].config, config("b", "^b$"));
+    }
+
+    #[tokio::test]
+    async fn removed_applications_save_their_checkpoint() {
+        let dir = env::temp_dir().join(format!("fefs-supervisor-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log_file = dir.join("b.log").display().to_string();
+        fs::write(&log_file, "").unwrap();
+        let checkpoint_dir = dir.join("checkpoints").display().to_string();
+        let (_shutdown_tx, shutdown) = Shutdown::new();
+        let context = ClientContext::new(Arc::new(status::new_statuses()), Arc::new(telemetry::new_tailers()), shutdown, Some(checkpoint_dir.clone()));
+        let b = config_in(&dir.display().to_string(), "b", "^b\\.log$");
+
+        let mut tailers = BTreeMap::new();
+        apply(vec![config("a", "^a$"), b], BTreeSet::new(), &mut tailers, DEFAULT_SHUTDOWN_GRACE_PERIOD, &context).await;
+        time::sleep(Duration::from_millis(300)).await;
+        let mut writer = fs::OpenOptions::new().append(true).open(&log_file).unwrap();
+        writeln!(writer, "a line").unwrap();
+        time::sleep(Duration::from_millis(300)).await;
+
+        apply(vec![config("a", "^a$")], BTreeSet::new(), &mut tailers, DEFAULT_SHUTDOWN_GRACE_PERIOD, &context).await;
+        let checkpoint = checkpoint::load(&checkpoint_dir, &application("b"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let checkpoint = checkpoint.unwrap();
+        assert_eq!(checkpoint.get_path(), log_file);
+        assert!(checkpoint.get_position() > 0);
+        // only the removed tailer was stopped
+        assert!(!context.is_shutting_down());
+        assert!(!tailers[&application("a")].handle.is_finished());
+    }
+
+    #[tokio::test]
+    async fn edits_to_the_configuration_file_are_applied() {
+        let dir = env::temp_dir().join(format!("fefs-reload-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("client.json").display().to_string();
+        let write = |configs: Vec<LogConfiguration>| fs::write(&path, json!({ "configs": configs }).to_string()).unwrap();
+        write(vec![config("a", "^a$")]);
+
+        let (shutdown_tx, shutdown) = Shutdown::new();
+        let context = ClientContext::new(Arc::new(status::new_statuses()), Arc::new(telemetry::new_tailers()), shutdown, None);
+        let supervisor = tokio::spawn(supervise(path.clone(), ClientConfiguration::load(&path).unwrap(), context.clone()));
+        // the watcher only reports changes made after it started
+        time::sleep(Duration::from_millis(300)).await;
+        write(vec![config("a", "^a$"), config("b", "^b$")]);
+
+        let started = time::timeout(Duration::from_secs(5), async {
+            while !context.get_tailers().lock().await.contains_key(&application("b")) {
+                time::sleep(Duration::from_millis(50)).await;
+            }
+        }).await;
+        let _ = shutdown_tx.send(true);
+        let stopped = time::timeout(DEFAULT_SHUTDOWN_GRACE_PERIOD, supervisor).await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(started.is_ok(), "the new application was not started");
+        assert!(matches!(stopped, Ok(Ok(()))), "the supervisor did not stop");
+    }
+}
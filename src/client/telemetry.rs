@@ -0,0 +1,140 @@
+use std::{collections::BTreeMap, sync::atomic::{AtomicU64, Ordering}, time::Instant};
+
+use tokio::sync::Mutex;
+
+use crate::{message::{Message, TailerStats}, Applicatiton};
+
+use super::{checkpoint::Checkpoint, requests::TailerHandle};
+
+// what a tailer and its connections did since the tailer started
+#[derive(Debug, Default)]
+pub struct Counters {
+    lines_read: AtomicU64,
+    bytes_read: AtomicU64,
+    lines_sent: AtomicU64,
+    lines_dropped: AtomicU64
+}
+
+impl Counters {
+    pub fn read(&self, lines: u64, bytes: u64) {
+        self.lines_read.fetch_add(lines, Ordering::Relaxed);
+        self.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self) {
+        self.lines_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self, lines: u64) {
+        self.lines_dropped.fetch_add(lines, Ordering::Relaxed);
+    }
+
+    pub fn get_lines_read(&self) -> u64 {
+        self.lines_read.load(Ordering::Relaxed)
+    }
+
+    pub fn get_bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    pub fn get_lines_sent(&self) -> u64 {
+        self.lines_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn get_lines_dropped(&self) -> u64 {
+        self.lines_dropped.load(Ordering::Relaxed)
+    }
+}
+
+// the running tailers, for the metrics
+pub type Tailers = Mutex<BTreeMap<Applicatiton, TailerHandle>>;
+
+pub fn new_tailers() -> Tailers {
+    Mutex::new(BTreeMap::new())
+}
+
+pub async fn add_tailer(tailers: &Tailers, application: &Applicatiton, tailer: TailerHandle) {
+    tailers.lock().await.insert(application.clone(), tailer);
+}
+
+pub async fn remove_tailer(tailers: &Tailers, application: &Applicatiton) {
+    tailers.lock().await.remove(application);
+}
+
+// the rest of a line that was cut short is sent again in full, but it's still the same line
+pub fn is_line(msg: &Message) -> bool {
+    msg.data().is_some_and(|data| !data.replaces_last_row())
+}
+
+// the counters as they were at the previous stats, for the rates in between
+pub struct Rates {
+    lines_read: u64,
+    bytes_read: u64,
+    at: Instant
+}
+
+impl Rates {
+    pub fn new(counters: &Counters) -> Self {
+        Self { lines_read: counters.get_lines_read(), bytes_read: counters.get_bytes_read(), at: Instant::now() }
+    }
+
+    pub async fn stats(&mut self, counters: &Counters, position: Option<Checkpoint>, reconnects: u64) -> TailerStats {
+        let lines_read = counters.get_lines_read();
+        let bytes_read = counters.get_bytes_read();
+        let elapsed = self.at.elapsed().as_secs_f64().max(f64::EPSILON);
+        let lines_per_sec = (lines_read - self.lines_read) as f64 / elapsed;
+        let bytes_per_sec = (bytes_read - self.bytes_read) as f64 / elapsed;
+        *self = Self { lines_read, bytes_read, at: Instant::now() };
+
+        let stats = TailerStats::new(lines_per_sec, bytes_per_sec, lines_read, counters.get_lines_sent(), counters.get_lines_dropped(), reconnects);
+        match position {
+            Some(position) => {
+                let file_size = tokio::fs::metadata(position.get_path()).await.map(|metadata| metadata.len()).ok();
+                stats.with_file(position.get_path().to_string(), position.get_position(), file_size)
+            }
+            None => stats,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::{message::DataMessage, Applicatiton};
+
+    use super::*;
+
+    #[test]
+    fn replaced_rows_are_not_lines() {
+        let application = Applicatiton::SinglePod("app".to_string());
+        assert!(is_line(&Message::Data(DataMessage::new("a line".to_string(), application.clone(), false))));
+        assert!(!is_line(&Message::Data(DataMessage::new("a longer line".to_string(), application, true))));
+        assert!(!is_line(&Message::ClientDisconnect));
+    }
+
+    #[tokio::test]
+    async fn stats_count_since_the_start_and_lag_behind_the_file() {
+        let path = env::temp_dir().join(format!("fefs-telemetry-test-{}", std::process::id()));
+        fs::write(&path, "0123456789").unwrap();
+        let counters = Counters::default();
+        counters.read(2, 4);
+        let mut rates = Rates::new(&counters);
+        counters.read(1, 2);
+        counters.sent();
+        counters.dropped(1);
+
+        let position = Checkpoint::new(path.display().to_string(), None, 6);
+        let stats = rates.stats(&counters, Some(position), 3).await;
+        fs::remove_file(&path).unwrap();
+
+        let stats = serde_json::to_value(stats).unwrap();
+        assert_eq!(stats["lines_read"], 3);
+        assert_eq!(stats["lines_sent"], 1);
+        assert_eq!(stats["lines_dropped"], 1);
+        assert_eq!(stats["reconnects"], 3);
+        assert_eq!(stats["file_size"], 10);
+        assert_eq!(stats["lag"], 4);
+        assert!(stats["lines_per_sec"].as_f64().unwrap() > 0.0);
+    }
+}
@@ -0,0 +1,291 @@
+use std::{collections::BTreeMap, fmt, fs};
+
+use log::warn;
+
+use crate::Applicatiton;
+
+use super::{configuration::LogConfiguration, paths};
+
+// a problem with one field of the configuration file, path is the json path of the field e.g. configs[1].log_file_dir
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigurationIssue {
+    path: String,
+    message: String,
+    // the application of the log configuration, when its name could be read
+    application: Option<Applicatiton>
+}
+
+impl ConfigurationIssue {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { path: path.into(), message: message.into(), application: None }
+    }
+
+    pub fn for_application(self, application: Option<Applicatiton>) -> Self {
+        Self { application, ..self }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn application(&self) -> Option<&Applicatiton> {
+        self.application.as_ref()
+    }
+}
+
+impl fmt::Display for ConfigurationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+// every problem with one log configuration, path is where it is in the configuration file
+pub fn validate(configuration: &LogConfiguration, path: &str) -> Vec<ConfigurationIssue> {
+    let mut issues = vec![];
+
+    if let Err(e) = regex::Regex::new(&configuration.get_log_file_name_regex()) {
+        issues.push(ConfigurationIssue::new(format!("{}.log_file_name_regex", path), e.to_string()));
+    }
+
+    let dirs = configuration.get_log_file_dirs();
+    if dirs.is_empty() {
+        issues.push(ConfigurationIssue::new(format!("{}.log_file_dir", path), "no directory configured, set log_file_dir or log_file_dirs"));
+    }
+    // like the tailer, a directory that can't be read is only a problem when none of them can,
+    // the others may show up later e.g. once a volume is mounted
+    let mut inaccessible = vec![];
+    for (dir, field) in dirs.iter().zip(configuration.get_log_file_dir_fields()) {
+        let field = format!("{}.{}", path, field);
+        let resolved = paths::resolve_dir(dir);
+        match fs::metadata(&resolved) {
+            Ok(metadata) if !metadata.is_dir() => {
+                inaccessible.push(ConfigurationIssue::new(field, format!("{} is not a directory", resolved.display())));
+            }
+            Ok(_) => {
+                if let Err(e) = fs::read_dir(&resolved) {
+                    inaccessible.push(ConfigurationIssue::new(field, format!("cannot read {}: {}", resolved.display(), e)));
+                }
+            }
+            Err(e) => {
+                inaccessible.push(ConfigurationIssue::new(field, format!("cannot access {}: {}", resolved.display(), e)));
+            }
+        }
+    }
+    if inaccessible.len() < dirs.len() {
+        for issue in inaccessible {
+            warn!("Skipping a directory of {}, {}", configuration.get_application().name(), issue);
+        }
+    } else {
+        issues.extend(inaccessible);
+    }
+
+    if configuration.get_max_file_age() == Some(std::time::Duration::ZERO) {
+        issues.push(ConfigurationIssue::new(format!("{}.max_file_age_secs", path), "must be greater than 0"));
+    }
+    if configuration.get_pause_buffer_max_age().is_zero() {
+        issues.push(ConfigurationIssue::new(format!("{}.pause_buffer_max_age_secs", path), "must be greater than 0"));
+    }
+    if configuration.get_stats_interval().is_zero() {
+        issues.push(ConfigurationIssue::new(format!("{}.stats_interval_secs", path), "must be greater than 0"));
+    }
+
+    let servers = configuration.get_servers();
+    if servers.is_empty() {
+        issues.push(ConfigurationIssue::new(format!("{}.servers", path), "no server configured, set servers or server_host and server_port"));
+    }
+    for (server, prefix) in servers.iter().zip(configuration.get_server_field_prefixes()) {
+        if server.get_host().is_empty() {
+            issues.push(ConfigurationIssue::new(format!("{}.{}host", path, prefix), "must not be empty"));
+        }
+        if server.get_port() == 0 {
+            issues.push(ConfigurationIssue::new(format!("{}.{}port", path, prefix), "must be between 1 and 65535"));
+        }
+    }
+    if configuration.has_partial_legacy_server() {
+        issues.push(ConfigurationIssue::new(format!("{}.server_port", path), "server_host and server_port must be set together"));
+    }
+
+    if configuration.get_channel_buffer() == 0 {
+        issues.push(ConfigurationIssue::new(format!("{}.channel_buffer", path), "must be greater than 0"));
+    }
+
+    if configuration.has_partial_client_identity() {
+        issues.push(ConfigurationIssue::new(format!("{}.client_certificate", path), "client_certificate and client_key must be set together"));
+    }
+
+    let reconnect = configuration.get_reconnect();
+    // without a delay a server that is down is retried in a busy loop
+    if reconnect.get_initial_delay().is_zero() {
+        issues.push(ConfigurationIssue::new(format!("{}.reconnect.initial_delay_ms", path), "must be greater than 0"));
+    } else if reconnect.get_initial_delay() > reconnect.get_max_delay() {
+        issues.push(ConfigurationIssue::new(format!("{}.reconnect.initial_delay_ms", path), "must not be greater than max_delay_ms"));
+    }
+    if reconnect.get_multiplier() < 1.0 {
+        issues.push(ConfigurationIssue::new(format!("{}.reconnect.multiplier", path), "must be at least 1.0"));
+    }
+    if !(0.0..=1.0).contains(&reconnect.get_jitter()) {
+        issues.push(ConfigurationIssue::new(format!("{}.reconnect.jitter", path), "must be between 0.0 and 1.0"));
+    }
+
+    issues
+}
+
+// keeps the valid configurations in order and reports every problem with the others,
+// an application configured twice is only tailed for the first configuration
+pub fn validate_all(configurations: Vec<(String, LogConfiguration)>) -> (Vec<LogConfiguration>, Vec<ConfigurationIssue>) {
+    let mut valid = vec![];
+    let mut issues = vec![];
+    let mut seen: BTreeMap<Applicatiton, String> = BTreeMap::new();
+
+    for (path, configuration) in configurations {
+        let mut configuration_issues = validate(&configuration, &path);
+
+        let application = configuration.get_application();
+        configuration_issues = configuration_issues.into_iter()
+            .map(|issue| issue.for_application(Some(application.clone())))
+            .collect();
+        match seen.get(&application) {
+            Some(first_path) => configuration_issues.push(ConfigurationIssue::new(
+                format!("{}.app_name", path),
+                format!("{} is already configured in {}", application.name(), first_path)
+            )),
+            None => {
+                seen.insert(application, path.clone());
+            }
+        }
+
+        if configuration_issues.is_empty() {
+            valid.push(configuration);
+        } else {
+            issues.extend(configuration_issues);
+        }
+    }
+
+    (valid, issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn configuration(overrides: Value) -> LogConfiguration {
+        let mut configuration = json!({
+            "app_name": { "SinglePod": "app" },
+            "log_file_dir": env::temp_dir(),
+            "log_file_name_regex": "^app.*\\.log$",
+            "server_host": "localhost",
+            "server_port": 8080,
+            "channel_buffer": 10
+        });
+        configuration.as_object_mut().unwrap().extend(overrides.as_object().unwrap().clone());
+        serde_json::from_value(configuration).unwrap()
+    }
+
+    fn fields(issues: &[ConfigurationIssue]) -> Vec<&str> {
+        issues.iter().map(ConfigurationIssue::path).collect()
+    }
+
+    #[test]
+    fn valid_configuration() {
+        assert_eq!(validate(&configuration(json!({})), "configs[0]"), vec![]);
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let issues = validate(&configuration(json!({
+            "log_file_name_regex": "(",
+            "log_file_dir": "/no/such/directory",
+            "channel_buffer": 0,
+            "client_certificate": "client.pem"
+        })), "configs[2]");
+        assert_eq!(fields(&issues), vec![
+            "configs[2].log_file_name_regex",
+            "configs[2].log_file_dir",
+            "configs[2].channel_buffer",
+            "configs[2].client_certificate"
+        ]);
+    }
+
+    #[test]
+    fn log_file_dir_must_be_a_directory() {
+        let issues = validate(&configuration(json!({ "log_file_dir": concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml") })), "configs[0]");
+        assert_eq!(fields(&issues), vec!["configs[0].log_file_dir"]);
+        assert!(issues[0].message().ends_with("is not a directory"));
+    }
+
+    #[test]
+    fn log_file_dirs() {
+        let missing = env::temp_dir().join(format!("fefs-validation-{}-missing", std::process::id()));
+        // skipped while another directory can be read
+        let issues = validate(&configuration(json!({ "log_file_dirs": [env::temp_dir(), missing] })), "configs[0]");
+        assert_eq!(issues, vec![]);
+
+        let issues = validate(&configuration(json!({ "log_file_dir": null })), "configs[0]");
+        assert_eq!(fields(&issues), vec!["configs[0].log_file_dir"]);
+    }
+
+    #[test]
+    fn servers() {
+        let issues = validate(&configuration(json!({ "server_host": null, "server_port": null })), "configs[0]");
+        assert_eq!(fields(&issues), vec!["configs[0].servers"]);
+
+        let issues = validate(&configuration(json!({ "server_port": null })), "configs[0]");
+        assert_eq!(fields(&issues), vec!["configs[0].servers", "configs[0].server_port"]);
+
+        let issues = validate(&configuration(json!({ "server_host": "", "server_port": 0 })), "configs[0]");
+        assert_eq!(fields(&issues), vec!["configs[0].server_host", "configs[0].server_port"]);
+
+        let issues = validate(&configuration(json!({ "servers": [{ "host": "backup", "port": 0, "path": "ws" }] })), "configs[0]");
+        assert_eq!(fields(&issues), vec!["configs[0].servers[0].port"]);
+    }
+
+    #[test]
+    fn reconnect() {
+        let issues = validate(&configuration(json!({ "reconnect": { "initial_delay_ms": 0 } })), "configs[0]");
+        assert_eq!(fields(&issues), vec!["configs[0].reconnect.initial_delay_ms"]);
+
+        let issues = validate(&configuration(json!({ "reconnect": { "initial_delay_ms": 5_000, "max_delay_ms": 1_000 } })), "configs[0]");
+        assert_eq!(fields(&issues), vec!["configs[0].reconnect.initial_delay_ms"]);
+
+        let issues = validate(&configuration(json!({ "reconnect": { "multiplier": 0.5, "jitter": 1.5 } })), "configs[0]");
+        assert_eq!(fields(&issues), vec!["configs[0].reconnect.multiplier", "configs[0].reconnect.jitter"]);
+    }
+
+    #[test]
+    fn max_file_age() {
+        let issues = validate(&configuration(json!({ "max_file_age_secs": 0 })), "configs[0]");
+        assert_eq!(fields(&issues), vec!["configs[0].max_file_age_secs"]);
+        assert_eq!(validate(&configuration(json!({ "max_file_age_secs": 3600, "file_selection": "Natural" })), "configs[0]"), vec![]);
+    }
+
+    #[test]
+    fn first_configuration_of_an_application_wins() {
+        let configurations = vec![
+            ("configs[0]".to_string(), configuration(json!({ "channel_buffer": 1 }))),
+            ("configs[1]".to_string(), configuration(json!({ "channel_buffer": 2 }))),
+            ("configs[2]".to_string(), configuration(json!({ "app_name": { "SinglePod": "other" }, "channel_buffer": 0 })))
+        ];
+
+        let (valid, issues) = validate_all(configurations);
+        assert_eq!(valid.len(), 1);
+        assert_eq!(valid[0].get_channel_buffer(), 1);
+        assert_eq!(fields(&issues), vec!["configs[1].app_name", "configs[2].channel_buffer"]);
+        assert_eq!(issues[0].message(), "app is already configured in configs[0]");
+    }
+
+    #[test]
+    fn no_directory_accessible() {
+        let missing = env::temp_dir().join(format!("fefs-validation-{}-missing", std::process::id()));
+        let file = env::current_exe().unwrap();
+        let issues = validate(&configuration(json!({ "log_file_dir": missing, "log_file_dirs": [file] })), "configs[0]");
+        assert_eq!(fields(&issues), vec!["configs[0].log_file_dir", "configs[0].log_file_dirs[0]"]);
+    }
+}
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 pub mod server;
 pub mod client;
 pub mod message;
+pub mod tls;
 
 pub fn hello_world(from: &str) {
     println!("Hello, world! I'm a {}!", from);
@@ -14,6 +15,12 @@ pub struct MultiPodApplication {
     pod_name: String
 }
 
+impl MultiPodApplication {
+    pub fn new(application: String, pod_name: String) -> Self {
+        Self { application, pod_name }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Applicatiton {
     SinglePod(String),
@@ -27,10 +34,17 @@ impl Applicatiton {
             Applicatiton::MultiPod(multi_pod) => multi_pod.application.clone()
         }
     }
+
+    pub fn pod_name(&self) -> Option<String> {
+        match self {
+            Applicatiton::SinglePod(_) => None,
+            Applicatiton::MultiPod(multi_pod) => Some(multi_pod.pod_name.clone())
+        }
+    }
 }
 
-impl ToString for Applicatiton {
-    fn to_string(&self) -> String {
-        serde_json::to_string(self).unwrap()
+impl std::fmt::Display for Applicatiton {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", serde_json::to_string(self).unwrap())
     }
 }
\ No newline at end of file
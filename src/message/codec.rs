@@ -0,0 +1,248 @@
+use std::io::Read;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::Message;
+
+pub const WIRE_ENCODING_HEADER: &str = "Wire-Encoding";
+
+const ZSTD_LEVEL: i32 = 3;
+// the largest message a frame may hold, decompressed or not
+pub const MAX_FRAME_SIZE: usize = 2_usize.pow(20);
+
+#[derive(Debug, Error)]
+pub enum CodecError {
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("messagepack encode error: {0}")]
+    MessagePackEncode(#[from] rmp_serde::encode::Error),
+    #[error("messagepack decode error: {0}")]
+    MessagePackDecode(#[from] rmp_serde::decode::Error),
+    #[error("compression error: {0}")]
+    Compression(#[from] std::io::Error),
+    #[error("message is larger than {0} bytes once decompressed")]
+    TooLarge(usize),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireFormat {
+    #[default]
+    Json,
+    MessagePack
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd
+}
+
+pub enum WireFrame {
+    Text(String),
+    Binary(Vec<u8>)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WireEncoding {
+    format: WireFormat,
+    compression: Compression
+}
+
+impl WireEncoding {
+    pub fn new(format: WireFormat, compression: Compression) -> Self {
+        Self { format, compression }
+    }
+
+    pub fn format(&self) -> WireFormat {
+        self.format
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.format != WireFormat::Json || self.compression != Compression::None
+    }
+
+    pub fn header_value(&self) -> String {
+        let format = match self.format {
+            WireFormat::Json => "json",
+            WireFormat::MessagePack => "msgpack",
+        };
+        match self.compression {
+            Compression::None => format.to_string(),
+            Compression::Zstd => format!("{}+zstd", format),
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('+');
+        let format = match parts.next()?.trim() {
+            "json" => WireFormat::Json,
+            "msgpack" => WireFormat::MessagePack,
+            _ => return None,
+        };
+        let compression = match parts.next().map(str::trim) {
+            None => Compression::None,
+            Some("zstd") => Compression::Zstd,
+            Some(_) => return None,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self { format, compression })
+    }
+
+    // the client offers encodings in order of preference, the first one we understand wins
+    pub fn negotiate(offered: Option<&str>) -> Self {
+        offered
+            .and_then(|offered| offered.split(',').find_map(Self::parse))
+            .unwrap_or_default()
+    }
+
+    // the client always offers json as a fallback so older servers keep working
+    pub fn offer(&self) -> String {
+        let preferred = self.header_value();
+        let fallback = Self::default().header_value();
+        if preferred == fallback {
+            preferred
+        } else {
+            format!("{}, {}", preferred, fallback)
+        }
+    }
+
+    pub fn encode(&self, message: &Message) -> Result<WireFrame, CodecError> {
+        if !self.is_binary() {
+            return Ok(WireFrame::Text(serde_json::to_string(message)?));
+        }
+
+        let bytes = match self.format {
+            WireFormat::Json => serde_json::to_vec(message)?,
+            WireFormat::MessagePack => rmp_serde::to_vec_named(message)?,
+        };
+        let bytes = match self.compression {
+            Compression::None => bytes,
+            Compression::Zstd => zstd::encode_all(bytes.as_slice(), ZSTD_LEVEL)?,
+        };
+        Ok(WireFrame::Binary(bytes))
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Result<Message, CodecError> {
+        let decompressed;
+        let bytes = match self.compression {
+            Compression::None => bytes,
+            // a small frame may decompress to far more than was sent, only the limit is read
+            Compression::Zstd => {
+                let mut buffer = vec![];
+                zstd::Decoder::new(bytes)?.take(MAX_FRAME_SIZE as u64 + 1).read_to_end(&mut buffer)?;
+                if buffer.len() > MAX_FRAME_SIZE {
+                    return Err(CodecError::TooLarge(MAX_FRAME_SIZE));
+                }
+                decompressed = buffer;
+                decompressed.as_slice()
+            }
+        };
+        let message = match self.format {
+            WireFormat::Json => serde_json::from_slice(bytes)?,
+            WireFormat::MessagePack => rmp_serde::from_slice(bytes)?,
+        };
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{message::{DataMessage, Replies, ReplyMessage, SystemMessage, SystemMessages}, Applicatiton};
+
+    use super::*;
+
+    const ENCODINGS: [WireEncoding; 4] = [
+        WireEncoding { format: WireFormat::Json, compression: Compression::None },
+        WireEncoding { format: WireFormat::Json, compression: Compression::Zstd },
+        WireEncoding { format: WireFormat::MessagePack, compression: Compression::None },
+        WireEncoding { format: WireFormat::MessagePack, compression: Compression::Zstd },
+    ];
+
+    fn messages() -> Vec<Message> {
+        let pod: Applicatiton = serde_json::from_str(r#"{"MultiPod": {"application": "app", "pod_name": "pod-0"}}"#).unwrap();
+        vec![
+            Message::Data(DataMessage::new("a line with ünïcode".to_string(), Applicatiton::SinglePod("app".to_string()), true)),
+            Message::System(SystemMessage::new(pod.clone(), SystemMessages::Start)),
+            Message::Reply(ReplyMessage::new(pod, 7, Replies::History("older line".to_string()))),
+            Message::ClientDisconnect,
+        ]
+    }
+
+    #[test]
+    fn parse_header_values() {
+        assert_eq!(WireEncoding::parse("json"), Some(WireEncoding::new(WireFormat::Json, Compression::None)));
+        assert_eq!(WireEncoding::parse("json+zstd"), Some(WireEncoding::new(WireFormat::Json, Compression::Zstd)));
+        assert_eq!(WireEncoding::parse(" msgpack + zstd "), Some(WireEncoding::new(WireFormat::MessagePack, Compression::Zstd)));
+        assert_eq!(WireEncoding::parse("msgpack"), Some(WireEncoding::new(WireFormat::MessagePack, Compression::None)));
+        assert_eq!(WireEncoding::parse("cbor"), None);
+        assert_eq!(WireEncoding::parse("json+gzip"), None);
+        assert_eq!(WireEncoding::parse("json+zstd+zstd"), None);
+        assert_eq!(WireEncoding::parse(""), None);
+    }
+
+    #[test]
+    fn header_value_parses_back() {
+        for encoding in ENCODINGS {
+            assert_eq!(WireEncoding::parse(&encoding.header_value()), Some(encoding));
+        }
+    }
+
+    #[test]
+    fn negotiate_takes_the_first_known_encoding() {
+        assert_eq!(WireEncoding::negotiate(None), WireEncoding::default());
+        assert_eq!(WireEncoding::negotiate(Some("cbor, msgpack+zstd, json")), WireEncoding::new(WireFormat::MessagePack, Compression::Zstd));
+        assert_eq!(WireEncoding::negotiate(Some("cbor, brotli")), WireEncoding::default());
+        let offered = WireEncoding::new(WireFormat::MessagePack, Compression::None).offer();
+        assert_eq!(offered, "msgpack, json");
+        assert_eq!(WireEncoding::negotiate(Some(&offered)), WireEncoding::new(WireFormat::MessagePack, Compression::None));
+        assert_eq!(WireEncoding::default().offer(), "json");
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        for encoding in ENCODINGS {
+            for message in messages() {
+                let bytes = match encoding.encode(&message).unwrap() {
+                    WireFrame::Text(text) => {
+                        assert!(!encoding.is_binary());
+                        text.into_bytes()
+                    }
+                    WireFrame::Binary(bytes) => {
+                        assert!(encoding.is_binary());
+                        bytes
+                    }
+                };
+                let decoded = encoding.decode(&bytes).unwrap();
+                assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&message).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn decompressed_size_is_limited() {
+        let encoding = WireEncoding::new(WireFormat::Json, Compression::Zstd);
+        let bomb = zstd::encode_all(vec![b' '; MAX_FRAME_SIZE + 1].as_slice(), ZSTD_LEVEL).unwrap();
+        assert!(bomb.len() < 1024);
+        assert!(matches!(encoding.decode(&bomb), Err(CodecError::TooLarge(MAX_FRAME_SIZE))));
+
+        let mut json = serde_json::to_vec(&messages()[0]).unwrap();
+        json.resize(MAX_FRAME_SIZE, b' ');
+        let largest = zstd::encode_all(json.as_slice(), ZSTD_LEVEL).unwrap();
+        assert!(encoding.decode(&largest).is_ok());
+    }
+
+    #[test]
+    fn decode_garbage_is_an_error() {
+        for encoding in ENCODINGS {
+            assert!(encoding.decode(b"\xff\x00 not a message").is_err());
+        }
+    }
+}
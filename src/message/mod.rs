@@ -3,16 +3,113 @@ use serde::{Deserialize, Serialize};
 
 use crate::Applicatiton;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub mod codec;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum SystemMessages {
     FileFound,
     FileRemoved,
-    NewFileFound,
+    // the tailer moved on from previous to current, after a rotation or a rollover to a newer file
+    NewFileFound { previous: String, current: String },
     TailingStarted,
     Start,
     Stop,
     Pause,
-    Resume
+    Resume,
+    // sent by a client sharing one connection between applications, before any message of the application
+    Register,
+    // something went wrong on the client, with what
+    Error(String),
+    // sent by the server, asks for the last lines of the file being tailed, the client answers with replies
+    Backfill { request_id: u64, lines: Option<usize>, since: Option<NaiveDateTime> },
+    // sent by the server, asks for the files the client could tail, counting their lines takes reading them
+    ListFiles {
+        request_id: u64,
+        #[serde(default)]
+        count_lines: bool
+    },
+    // sent by the server, the client tails the file instead of the one it picked until it goes away
+    SwitchFile { request_id: u64, path: String },
+    // sent by the server, the client sends the whole file once as History replies
+    StreamFile { request_id: u64, path: String },
+    // sent by the server, the client greps the files it could tail, oldest first, and answers with matches
+    Search { request_id: u64, pattern: String, from: Option<NaiveDateTime>, to: Option<NaiveDateTime>, limit: Option<usize> },
+    // sent by the client every stats interval, whether paused or not
+    Stats(TailerStats)
+}
+
+// how a client's tailer is doing, rates are since the previous stats of the connection
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct TailerStats {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    position: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file_size: Option<u64>,
+    // bytes written to the file that weren't read yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lag: Option<u64>,
+    lines_per_sec: f64,
+    bytes_per_sec: f64,
+    lines_read: u64,
+    lines_sent: u64,
+    // lines that never reached the server, left out of a full pause buffer or a mirror that fell behind
+    lines_dropped: u64,
+    reconnects: u64
+}
+
+impl TailerStats {
+    pub fn new(lines_per_sec: f64, bytes_per_sec: f64, lines_read: u64, lines_sent: u64, lines_dropped: u64, reconnects: u64) -> Self {
+        Self { file: None, position: None, file_size: None, lag: None, lines_per_sec, bytes_per_sec, lines_read, lines_sent, lines_dropped, reconnects }
+    }
+
+    // the size is left out when the file couldn't be looked at
+    pub fn with_file(self, file: String, position: u64, file_size: Option<u64>) -> Self {
+        let lag = file_size.map(|file_size| file_size.saturating_sub(position));
+        Self { file: Some(file), position: Some(position), file_size, lag, ..self }
+    }
+}
+
+// a file matching the log file directories and regex of a client
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct FileInfo {
+    path: String,
+    size: u64,
+    modified: NaiveDateTime,
+    // the file being tailed
+    tailed: bool,
+    compressed: bool,
+    // decompressed, when they were asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lines: Option<u64>
+}
+
+impl FileInfo {
+    pub fn new(path: String, size: u64, modified: NaiveDateTime, tailed: bool, compressed: bool) -> Self {
+        Self { path, size, modified, tailed, compressed, lines: None }
+    }
+
+    pub fn with_lines(self, lines: u64) -> Self {
+        Self { lines: Some(lines), ..self }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+// the client's answers to a request of the server, they only go to whoever asked
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub enum Replies {
+    // a line that was in the file before the request
+    History(String),
+    // the whole answer to ListFiles
+    Files(Vec<FileInfo>),
+    // a line found by a Search, counting lines from 1
+    Match { path: String, line_number: u64, line: String },
+    Done,
+    Error(String)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -34,6 +131,16 @@ pub struct SystemMessage {
     timestamp: NaiveDateTime
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReplyMessage {
+    #[serde(rename = "type")]
+    message_type: String,
+    application: Applicatiton,
+    request_id: u64,
+    reply: Replies,
+    timestamp: NaiveDateTime
+}
+
 impl DataMessage {
     pub fn new(row: String, application: Applicatiton, replace_last_row: bool) -> Self {
         Self { message_type: "Data".to_string(), row, application, replace_last_row, timestamp: chrono::Utc::now().naive_utc() }
@@ -42,6 +149,18 @@ impl DataMessage {
     pub fn row(&self) -> &str {
         &self.row
     }
+
+    pub fn replaces_last_row(&self) -> bool {
+        self.replace_last_row
+    }
+
+    pub fn timestamp(&self) -> NaiveDateTime {
+        self.timestamp
+    }
+
+    pub fn application(&self) -> &Applicatiton {
+        &self.application
+    }
 }
 
 impl SystemMessage {
@@ -52,12 +171,44 @@ impl SystemMessage {
     pub fn message(&self) -> &SystemMessages {
         &self.message
     }
+
+    pub fn timestamp(&self) -> NaiveDateTime {
+        self.timestamp
+    }
+
+    pub fn application(&self) -> &Applicatiton {
+        &self.application
+    }
+}
+
+impl ReplyMessage {
+    pub fn new(application: Applicatiton, request_id: u64, reply: Replies) -> Self {
+        Self { message_type: "Reply".to_string(), application, request_id, reply, timestamp: chrono::Utc::now().naive_utc() }
+    }
+
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+
+    pub fn reply(&self) -> &Replies {
+        &self.reply
+    }
+
+    pub fn application(&self) -> &Applicatiton {
+        &self.application
+    }
+
+    // nothing follows the last reply to a request
+    pub fn is_last(&self) -> bool {
+        matches!(self.reply, Replies::Files(_) | Replies::Done | Replies::Error(_))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Message {
     Data(DataMessage),
     System(SystemMessage),
+    Reply(ReplyMessage),
     ClientDisconnect
 }
 
@@ -75,4 +226,13 @@ impl Message {
             _ => None
         }
     }
+
+    pub fn application(&self) -> Option<&Applicatiton> {
+        match self {
+            Message::Data(data) => Some(data.application()),
+            Message::System(system) => Some(system.application()),
+            Message::Reply(reply) => Some(reply.application()),
+            Message::ClientDisconnect => None
+        }
+    }
 }
\ No newline at end of file
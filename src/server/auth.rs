@@ -0,0 +1,325 @@
+use std::{collections::BTreeMap, env, fs, io};
+
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use rustls::pki_types::CertificateDer;
+use serde::Deserialize;
+use thiserror::Error;
+use x509_parser::extensions::GeneralName;
+
+use crate::Applicatiton;
+
+pub const ALL_APPLICATIONS: &str = "*";
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("no token provided")]
+    MissingToken,
+    #[error("invalid token: {0}")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+    #[error("unknown token")]
+    UnknownToken,
+    #[error("token is not allowed to publish {0}")]
+    NotAllowed(String),
+    #[error("no client certificate provided")]
+    MissingCertificate,
+    #[error("invalid client certificate: {0}")]
+    InvalidCertificate(String),
+    #[error("client certificate is not allowed to publish {0}")]
+    CertificateNotAllowed(String),
+}
+
+// the verified certificate a client presented during the tls handshake, stored with the connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate(pub CertificateDer<'static>);
+
+// the bearer token and certificate a client connected with
+pub type Credential = (Option<String>, Option<ClientCertificate>);
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    // application names, or "*" for all of them
+    applications: Vec<String>
+}
+
+pub struct Authenticator {
+    pre_shared_keys: Vec<String>,
+    jwt_key: Option<DecodingKey>,
+    // certificate subject, common name or subject alternative name to application names, or "*" for all of them
+    certificate_applications: Option<BTreeMap<String, Vec<String>>>
+}
+
+impl Authenticator {
+    // AUTH_TOKENS is a comma separated list of pre-shared keys allowed to publish any application,
+    // AUTH_JWT_SECRET is the HS256 secret of tokens that name the applications they may publish,
+    // TLS_CLIENT_APPLICATIONS_PATH is a json file mapping client certificate identities to applications
+    pub fn from_env() -> io::Result<Self> {
+        let pre_shared_keys = env::var("AUTH_TOKENS")
+            .map(|tokens| tokens.split(',').map(str::trim).filter(|token| !token.is_empty()).map(String::from).collect())
+            .unwrap_or_default();
+        let jwt_secret = env::var("AUTH_JWT_SECRET").ok();
+        let certificate_applications = match env::var("TLS_CLIENT_APPLICATIONS_PATH") {
+            Ok(path) => Some(serde_json::from_str(&fs::read_to_string(path)?)?),
+            Err(_) => None,
+        };
+        Ok(Self::new(pre_shared_keys, jwt_secret.as_deref(), certificate_applications))
+    }
+
+    pub fn new(pre_shared_keys: Vec<String>, jwt_secret: Option<&str>, certificate_applications: Option<BTreeMap<String, Vec<String>>>) -> Self {
+        let jwt_key = jwt_secret.map(|secret| DecodingKey::from_secret(secret.as_bytes()));
+        Self { pre_shared_keys, jwt_key, certificate_applications }
+    }
+
+    pub fn is_token_enabled(&self) -> bool {
+        !self.pre_shared_keys.is_empty() || self.jwt_key.is_some()
+    }
+
+    pub fn is_certificate_enabled(&self) -> bool {
+        self.certificate_applications.is_some()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_token_enabled() || self.is_certificate_enabled()
+    }
+
+    // whether the client may connect at all, before it names any application: a known pre-shared key or a
+    // jwt signed with the secret, and a certificate when one is needed
+    pub fn authenticate(&self, token: Option<&str>, certificate: Option<&ClientCertificate>) -> Result<(), AuthError> {
+        if self.is_token_enabled() {
+            let token = token.ok_or(AuthError::MissingToken)?;
+            if !self.pre_shared_keys.iter().any(|key| key == token) {
+                let jwt_key = self.jwt_key.as_ref().ok_or(AuthError::UnknownToken)?;
+                jsonwebtoken::decode::<Claims>(token, jwt_key, &Validation::new(Algorithm::HS256))?;
+            }
+        }
+        if self.is_certificate_enabled() && certificate.is_none() {
+            return Err(AuthError::MissingCertificate);
+        }
+        Ok(())
+    }
+
+    pub fn authorize(&self, token: Option<&str>, certificate: Option<&ClientCertificate>, application: &Applicatiton) -> Result<(), AuthError> {
+        self.authorize_token(token, application)?;
+        self.authorize_certificate(certificate, application)
+    }
+
+    fn authorize_token(&self, token: Option<&str>, application: &Applicatiton) -> Result<(), AuthError> {
+        if !self.is_token_enabled() {
+            return Ok(());
+        }
+
+        let token = token.ok_or(AuthError::MissingToken)?;
+        if self.pre_shared_keys.iter().any(|key| key == token) {
+            return Ok(());
+        }
+
+        let jwt_key = self.jwt_key.as_ref().ok_or(AuthError::NotAllowed(application.name()))?;
+        let claims = jsonwebtoken::decode::<Claims>(token, jwt_key, &Validation::new(Algorithm::HS256))?.claims;
+        let allowed = claims.applications.iter().any(|allowed| allowed == ALL_APPLICATIONS || *allowed == application.name());
+        if allowed {
+            Ok(())
+        } else {
+            Err(AuthError::NotAllowed(application.name()))
+        }
+    }
+
+    fn authorize_certificate(&self, certificate: Option<&ClientCertificate>, application: &Applicatiton) -> Result<(), AuthError> {
+        let certificate_applications = match &self.certificate_applications {
+            Some(certificate_applications) => certificate_applications,
+            None => return Ok(()),
+        };
+
+        let certificate = certificate.ok_or(AuthError::MissingCertificate)?;
+        let allowed = certificate_identities(certificate)?
+            .iter()
+            .filter_map(|identity| certificate_applications.get(identity))
+            .flatten()
+            .any(|allowed| allowed == ALL_APPLICATIONS || *allowed == application.name());
+        if allowed {
+            Ok(())
+        } else {
+            Err(AuthError::CertificateNotAllowed(application.name()))
+        }
+    }
+}
+
+fn certificate_identities(certificate: &ClientCertificate) -> Result<Vec<String>, AuthError> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(certificate.0.as_ref())
+        .map_err(|e| AuthError::InvalidCertificate(e.to_string()))?;
+
+    let mut identities = vec![certificate.subject().to_string()];
+    identities.extend(certificate.subject().iter_common_name().filter_map(|name| name.as_str().ok()).map(String::from));
+    if let Ok(Some(alternative_names)) = certificate.subject_alternative_name() {
+        for name in alternative_names.value.general_names.iter() {
+            match name {
+                GeneralName::DNSName(name) | GeneralName::RFC822Name(name) | GeneralName::URI(name) => identities.push(name.to_string()),
+                _ => {}
+            }
+        }
+    }
+    Ok(identities)
+}
+
+pub fn bearer_token(value: &str) -> Option<&str> {
+    value.strip_prefix("Bearer ").map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+
+    const SECRET: &str = "jwt secret";
+
+    fn authenticator(pre_shared_keys: &[&str], jwt_secret: Option<&str>) -> Authenticator {
+        Authenticator::new(pre_shared_keys.iter().map(|key| key.to_string()).collect(), jwt_secret, None)
+    }
+
+    // expires_in is in seconds, negative for a token that already expired
+    fn jwt(secret: &str, applications: &[&str], expires_in: i64) -> String {
+        let claims = json!({ "applications": applications, "exp": chrono::Utc::now().timestamp() + expires_in });
+        jsonwebtoken::encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+    }
+
+    fn application(name: &str) -> Applicatiton {
+        Applicatiton::SinglePod(name.to_string())
+    }
+
+    #[test]
+    fn disabled_lets_everyone_in() {
+        let authenticator = authenticator(&[], None);
+        assert!(!authenticator.is_enabled());
+        assert!(authenticator.authorize(None, None, &application("app")).is_ok());
+    }
+
+    #[test]
+    fn pre_shared_key() {
+        let authenticator = authenticator(&["key 1", "key 2"], None);
+        assert!(authenticator.authorize(Some("key 2"), None, &application("app")).is_ok());
+        assert!(matches!(authenticator.authorize(None, None, &application("app")), Err(AuthError::MissingToken)));
+        assert!(matches!(authenticator.authorize(Some("key 3"), None, &application("app")), Err(AuthError::NotAllowed(_))));
+    }
+
+    #[test]
+    fn jwt_names_the_applications() {
+        let authenticator = authenticator(&[], Some(SECRET));
+        let token = jwt(SECRET, &["app", "other"], 3600);
+        assert!(authenticator.authorize(Some(&token), None, &application("app")).is_ok());
+        assert!(authenticator.authorize(Some(&token), None, &application("other")).is_ok());
+        assert!(matches!(authenticator.authorize(Some(&token), None, &application("third")), Err(AuthError::NotAllowed(name)) if name == "third"));
+
+        let token = jwt(SECRET, &[ALL_APPLICATIONS], 3600);
+        assert!(authenticator.authorize(Some(&token), None, &application("third")).is_ok());
+    }
+
+    #[test]
+    fn jwt_applies_to_every_pod() {
+        let authenticator = authenticator(&[], Some(SECRET));
+        let pod: Applicatiton = serde_json::from_value(json!({ "MultiPod": { "application": "app", "pod_name": "pod-0" } })).unwrap();
+        assert!(authenticator.authorize(Some(&jwt(SECRET, &["app"], 3600)), None, &pod).is_ok());
+    }
+
+    #[test]
+    fn expired_jwt() {
+        let authenticator = authenticator(&[], Some(SECRET));
+        let token = jwt(SECRET, &["app"], -3600);
+        assert!(matches!(authenticator.authorize(Some(&token), None, &application("app")), Err(AuthError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn jwt_signed_with_another_secret() {
+        let authenticator = authenticator(&[], Some(SECRET));
+        let token = jwt("another secret", &["app"], 3600);
+        assert!(matches!(authenticator.authorize(Some(&token), None, &application("app")), Err(AuthError::InvalidToken(_))));
+        assert!(matches!(authenticator.authorize(Some("not a jwt"), None, &application("app")), Err(AuthError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn pre_shared_keys_and_jwt_together() {
+        let authenticator = authenticator(&["key"], Some(SECRET));
+        assert!(authenticator.authorize(Some("key"), None, &application("app")).is_ok());
+        assert!(authenticator.authorize(Some(&jwt(SECRET, &["app"], 3600)), None, &application("app")).is_ok());
+    }
+
+    #[test]
+    fn connecting_needs_a_known_token() {
+        let authenticator = authenticator(&["key"], Some(SECRET));
+        assert!(authenticator.authenticate(Some("key"), None).is_ok());
+        assert!(authenticator.authenticate(Some(&jwt(SECRET, &["app"], 3600)), None).is_ok());
+        assert!(matches!(authenticator.authenticate(None, None), Err(AuthError::MissingToken)));
+        assert!(matches!(authenticator.authenticate(Some(&jwt(SECRET, &["app"], -3600)), None), Err(AuthError::InvalidToken(_))));
+        assert!(matches!(self::authenticator(&["key"], None).authenticate(Some("other"), None), Err(AuthError::UnknownToken)));
+        assert!(self::authenticator(&[], None).authenticate(None, None).is_ok());
+    }
+
+    #[test]
+    fn bearer_tokens() {
+        assert_eq!(bearer_token("Bearer abc"), Some("abc"));
+        assert_eq!(bearer_token("Bearer  abc "), Some("abc"));
+        assert_eq!(bearer_token("Basic abc"), None);
+    }
+
+    fn client_certificate() -> ClientCertificate {
+        let path = format!("{}/tests/fixtures/tls/client.pem", env!("CARGO_MANIFEST_DIR"));
+        ClientCertificate(crate::tls::load_certificates(&path).unwrap().remove(0))
+    }
+
+    fn certificate_authenticator(certificate_applications: &[(&str, &[&str])]) -> Authenticator {
+        let certificate_applications = certificate_applications
+            .iter()
+            .map(|(identity, applications)| (identity.to_string(), applications.iter().map(|name| name.to_string()).collect()))
+            .collect();
+        Authenticator::new(Vec::new(), None, Some(certificate_applications))
+    }
+
+    #[test]
+    fn certificate_identities_of_the_fixture() {
+        let identities = certificate_identities(&client_certificate()).unwrap();
+        assert_eq!(identities, ["O=fefs, CN=collector", "collector", "collector.fefs.test", "spiffe://fefs/collector"]);
+    }
+
+    #[test]
+    fn certificate_subject_and_alternative_names_map_to_applications() {
+        let certificate = client_certificate();
+        for identity in ["O=fefs, CN=collector", "collector", "collector.fefs.test", "spiffe://fefs/collector"] {
+            let authenticator = certificate_authenticator(&[(identity, &["app"]), ("someone else", &["other"])]);
+            assert!(authenticator.is_enabled());
+            assert!(authenticator.authorize(None, Some(&certificate), &application("app")).is_ok(), "{}", identity);
+            assert!(matches!(authenticator.authorize(None, Some(&certificate), &application("other")), Err(AuthError::CertificateNotAllowed(name)) if name == "other"));
+        }
+
+        let authenticator = certificate_authenticator(&[("collector.fefs.test", &[ALL_APPLICATIONS])]);
+        assert!(authenticator.authorize(None, Some(&certificate), &application("other")).is_ok());
+    }
+
+    #[test]
+    fn missing_certificate_is_rejected() {
+        let authenticator = certificate_authenticator(&[("collector", &["app"])]);
+        assert!(matches!(authenticator.authorize(None, None, &application("app")), Err(AuthError::MissingCertificate)));
+    }
+
+    #[test]
+    fn connecting_needs_a_certificate() {
+        let authenticator = certificate_authenticator(&[("collector", &["app"])]);
+        assert!(authenticator.authenticate(None, Some(&client_certificate())).is_ok());
+        assert!(matches!(authenticator.authenticate(None, None), Err(AuthError::MissingCertificate)));
+    }
+
+    #[test]
+    fn invalid_certificate_is_rejected() {
+        let authenticator = certificate_authenticator(&[("collector", &["app"])]);
+        let certificate = ClientCertificate(CertificateDer::from(b"not a certificate".to_vec()));
+        assert!(matches!(authenticator.authorize(None, Some(&certificate), &application("app")), Err(AuthError::InvalidCertificate(_))));
+    }
+
+    #[test]
+    fn certificate_and_token_are_both_required() {
+        let mut authenticator = certificate_authenticator(&[("collector", &["app"])]);
+        authenticator.pre_shared_keys = vec!["key".to_string()];
+        let certificate = client_certificate();
+        assert!(authenticator.authorize(Some("key"), Some(&certificate), &application("app")).is_ok());
+        assert!(matches!(authenticator.authorize(None, Some(&certificate), &application("app")), Err(AuthError::MissingToken)));
+        assert!(matches!(authenticator.authorize(Some("key"), None, &application("app")), Err(AuthError::MissingCertificate)));
+    }
+}
@@ -1,11 +1,51 @@
 use std::collections::BTreeMap;
 
+use actix_ws::Session;
 use tokio::sync::{broadcast::Sender, Mutex};
 
 use crate::{message::Message, Applicatiton};
 
-pub type Broadcasters = Mutex<BTreeMap<Applicatiton, Sender<Message>>>;
+use super::{auth::Credential, stats::ApplicationStats};
+
+// where an application's messages go to its viewers, and the connection of the client publishing it,
+// for viewers asking the client for something, and the last stats the client sent
+#[derive(Clone)]
+pub struct Broadcaster {
+    tx: Sender<Message>,
+    session: Session,
+    // what the publishing client connected with, only the same may register the application again
+    credential: Credential,
+    stats: Option<ApplicationStats>
+}
+
+impl Broadcaster {
+    pub fn new(tx: Sender<Message>, session: Session, credential: Credential) -> Self {
+        Self { tx, session, credential, stats: None }
+    }
+
+    pub fn get_tx(&self) -> &Sender<Message> {
+        &self.tx
+    }
+
+    pub fn get_session(&self) -> Session {
+        self.session.clone()
+    }
+
+    pub fn get_credential(&self) -> &Credential {
+        &self.credential
+    }
+
+    pub fn get_stats(&self) -> Option<&ApplicationStats> {
+        self.stats.as_ref()
+    }
+
+    pub fn set_stats(&mut self, stats: ApplicationStats) {
+        self.stats = Some(stats);
+    }
+}
+
+pub type Broadcasters = Mutex<BTreeMap<Applicatiton, Broadcaster>>;
 
 pub fn new_broadcasters() -> Broadcasters {
     Mutex::new(BTreeMap::new())
-}
\ No newline at end of file
+}
@@ -0,0 +1,124 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use actix_web::{body::MessageBody, get, post, web, HttpResponse, Responder};
+use futures::{future, stream::{self, StreamExt}};
+use log::{error, info};
+use tokio::{sync::mpsc, time};
+
+use crate::message::{Replies, ReplyMessage, SystemMessages};
+
+use super::{application_query, broadcaster::Broadcasters, optional_query, request, reply_stream, requests::Requests, REPLY_TIMEOUT, SCAN_REPLY_TIMEOUT};
+
+// the files matching the log file directories and regex of the client publishing the application,
+// with lines=true along with how many lines they have
+#[get("/api/files")]
+pub async fn list_files(broadcasters: web::Data<Arc<Broadcasters>>, requests: web::Data<Arc<Requests>>, query: web::Query<HashMap<String, String>>) -> impl Responder {
+    let application = match application_query(&query) {
+        Some(application) => application,
+        None => return HttpResponse::BadRequest().finish(),
+    };
+    let count_lines = match optional_query::<bool>(&query, "lines") {
+        Ok(count_lines) => count_lines.unwrap_or_default(),
+        Err(()) => return HttpResponse::BadRequest().finish(),
+    };
+    let list = |request_id| SystemMessages::ListFiles { request_id, count_lines };
+    let (request_id, mut replies) = match request(&broadcasters, &requests, &application, list).await {
+        Some(request) => request,
+        None => return HttpResponse::BadRequest().finish(),
+    };
+    let timeout = if count_lines { SCAN_REPLY_TIMEOUT } else { REPLY_TIMEOUT };
+    match next_reply(&mut replies, &requests, request_id, timeout).await.map(|reply| reply.reply().clone()) {
+        Some(Replies::Files(files)) => HttpResponse::Ok().json(files),
+        reply => failed(reply),
+    }
+}
+
+// the client tails the file instead of the one it picked, until the file goes away
+#[post("/api/switch")]
+pub async fn switch_file(broadcasters: web::Data<Arc<Broadcasters>>, requests: web::Data<Arc<Requests>>, query: web::Query<HashMap<String, String>>) -> impl Responder {
+    let application = match application_query(&query) {
+        Some(application) => application,
+        None => return HttpResponse::BadRequest().finish(),
+    };
+    let path = match query.get("path") {
+        Some(path) => path.clone(),
+        None => {
+            error!("No path parameter provided");
+            return HttpResponse::BadRequest().finish();
+        }
+    };
+    info!("Switching application {} to {}", application.name(), path);
+    let (request_id, mut replies) = match request(&broadcasters, &requests, &application, |request_id| SystemMessages::SwitchFile { request_id, path }).await {
+        Some(request) => request,
+        None => return HttpResponse::BadRequest().finish(),
+    };
+    match next_reply(&mut replies, &requests, request_id, REPLY_TIMEOUT).await.map(|reply| reply.reply().clone()) {
+        Some(Replies::Done) => HttpResponse::Ok().finish(),
+        reply => failed(reply),
+    }
+}
+
+// the whole file as plain text, read by the client once
+#[get("/api/file")]
+pub async fn stream_file(broadcasters: web::Data<Arc<Broadcasters>>, requests: web::Data<Arc<Requests>>, query: web::Query<HashMap<String, String>>) -> impl Responder {
+    let application = match application_query(&query) {
+        Some(application) => application,
+        None => return HttpResponse::BadRequest().finish(),
+    };
+    let path = match query.get("path") {
+        Some(path) => path.clone(),
+        None => {
+            error!("No path parameter provided");
+            return HttpResponse::BadRequest().finish();
+        }
+    };
+    let (request_id, mut replies) = match request(&broadcasters, &requests, &application, |request_id| SystemMessages::StreamFile { request_id, path }).await {
+        Some(request) => request,
+        None => return HttpResponse::BadRequest().finish(),
+    };
+
+    // the status depends on whether the client could open the file at all
+    let first = match next_reply(&mut replies, &requests, request_id, REPLY_TIMEOUT).await {
+        Some(reply) if matches!(reply.reply(), Replies::History(_)) => reply,
+        Some(reply) if matches!(reply.reply(), Replies::Done) => return HttpResponse::Ok().finish(),
+        reply => return failed(reply.map(|reply| reply.reply().clone())),
+    };
+    let body = stream::once(future::ready(file_line(&first)))
+        .chain(reply_stream(replies, Arc::clone(&requests), request_id, REPLY_TIMEOUT, file_line));
+
+    HttpResponse::Ok()
+        .append_header(("content-type", "text/plain; charset=utf-8"))
+        .streaming(body)
+}
+
+async fn next_reply(replies: &mut mpsc::Receiver<ReplyMessage>, requests: &Requests, request_id: u64, timeout: Duration) -> Option<ReplyMessage> {
+    match time::timeout(timeout, replies.recv()).await {
+        Ok(Some(reply)) => Some(reply),
+        Ok(None) => None,
+        Err(_) => {
+            error!("Request {} timed out", request_id);
+            requests.close(request_id).await;
+            None
+        }
+    }
+}
+
+fn failed(reply: Option<Replies>) -> HttpResponse {
+    match reply {
+        Some(Replies::Error(e)) => HttpResponse::BadRequest().body(e),
+        Some(reply) => {
+            error!("Unexpected reply: {:?}", reply);
+            HttpResponse::BadGateway().finish()
+        }
+        None => HttpResponse::GatewayTimeout().finish(),
+    }
+}
+
+// the body stops short with the error if the client can't read the file to the end
+fn file_line(reply: &ReplyMessage) -> Result<actix_web::web::Bytes, String> {
+    match reply.reply() {
+        Replies::History(line) => format!("{}\n", line).try_into_bytes(),
+        Replies::Error(e) => Err(e.clone()),
+        _ => Ok(actix_web::web::Bytes::new()),
+    }
+}
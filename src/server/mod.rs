@@ -1,52 +1,71 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
-
-use actix_web::{body::MessageBody, get, rt, web, Error, HttpRequest, HttpResponse, Responder, Result};
-use actix_ws::{AggregatedMessage, ProtocolError, Session};
-use broadcaster::Broadcasters;
-use log::{error, info, trace};
-use tokio::{sync::broadcast::{self, Sender}, time::sleep};
-use futures::{future, stream::StreamExt};
+use std::{collections::{BTreeMap, HashMap}, str::FromStr, sync::Arc, time::Duration};
+
+use actix_web::{body::MessageBody, get, http::header::{HeaderName, HeaderValue}, rt, web, Error, HttpRequest, HttpResponse, Responder, Result};
+use actix_ws::{AggregatedMessage, CloseCode, CloseReason, ProtocolError, Session};
+use auth::{Authenticator, ClientCertificate};
+use broadcaster::{Broadcaster, Broadcasters};
+use chrono::NaiveDateTime;
+use log::{error, info, trace, warn};
+use requests::Requests;
+use stats::ApplicationStats;
+use tokio::{sync::{broadcast::{self, Sender}, mpsc, Mutex}, time::{self, sleep}};
+use futures::{future, stream::{self, BoxStream, StreamExt}};
 use tokio_stream::wrappers::BroadcastStream;
 
-use crate::{message::{Message, SystemMessage, SystemMessages}, Applicatiton};
+use crate::{message::{codec::{WireEncoding, MAX_FRAME_SIZE, WIRE_ENCODING_HEADER}, Message, ReplyMessage, SystemMessage, SystemMessages, TailerStats}, Applicatiton};
 
+pub mod auth;
 pub mod broadcaster;
+pub mod requests;
+pub mod files;
+pub mod search;
+pub mod stats;
+
+// how long to wait for the next reply of a client before giving up on the rest
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+// searching or counting lines can go through a lot of a file between two replies
+const SCAN_REPLY_TIMEOUT: Duration = Duration::from_secs(60);
 
+// with lines=N or since=2026-10-19T12:00:00 the viewer first gets the last lines of the file from the client
 #[get("/api/sse")]
-pub async fn data_outbound(_req: HttpRequest, broadcasters: web::Data<Arc<Broadcasters>>, query: web::Query<HashMap<String, String>>,) -> impl Responder {
-    let application = match query.get("application") {
-        Some(app_str) => {
-            match serde_json::from_str(app_str) {
-                Ok(app) => app,
-                Err(e) => {
-                    error!("Failed to parse application JSON: {}", e);
-                    return HttpResponse::BadRequest().finish();
-                }
-            }
-        },
-        None => {
-            error!("No application parameter provided");
-            return HttpResponse::BadRequest().finish();
-        }
+pub async fn data_outbound(_req: HttpRequest, broadcasters: web::Data<Arc<Broadcasters>>, requests: web::Data<Arc<Requests>>, query: web::Query<HashMap<String, String>>,) -> impl Responder {
+    let application = match application_query(&query) {
+        Some(application) => application,
+        None => return HttpResponse::BadRequest().finish(),
     };
-    
-    let broadcasters = broadcasters.lock().await;
-    let rx = match broadcasters.get(&application) {
-        Some(tx) => tx.subscribe(),
+
+    let (lines, since) = match (optional_query::<usize>(&query, "lines"), optional_query::<NaiveDateTime>(&query, "since")) {
+        (Ok(lines), Ok(since)) => (lines, since),
+        _ => return HttpResponse::BadRequest().finish(),
+    };
+
+    let locked_broadcasters = broadcasters.lock().await;
+    let rx = match locked_broadcasters.get(&application) {
+        Some(broadcaster) => broadcaster.get_tx().subscribe(),
         None => {
             error!("No broadcaster found for application: {}", application.name());
             return HttpResponse::BadRequest().finish();
         },
     };
-    drop(broadcasters);
+    drop(locked_broadcasters);
 
-    let stream = BroadcastStream::new(rx)
-    .take_while(|msg| future::ready(
-        match msg {
-            Ok(Message::ClientDisconnect) => false,
-            Err(_) => false,
-            _ => true,
+    // subscribed first, so nothing falls between the backfill and what follows it
+    let history = if lines.is_some() || since.is_some() {
+        let backfill = |request_id| SystemMessages::Backfill { request_id, lines, since };
+        match request(&broadcasters, &requests, &application, backfill).await {
+            Some((request_id, replies)) => {
+                info!("Requested backfill {} of application: {}", request_id, application.name());
+                reply_stream(replies, Arc::clone(&requests), request_id, REPLY_TIMEOUT, reply_event)
+            }
+            None => return HttpResponse::BadRequest().finish(),
         }
+    } else {
+        stream::empty().boxed()
+    };
+
+    let live = BroadcastStream::new(rx)
+    .take_while(|msg| future::ready(
+        !matches!(msg, Ok(Message::ClientDisconnect) | Err(_))
     ))
     .map(|msg| {
         match msg {
@@ -61,9 +80,10 @@ pub async fn data_outbound(_req: HttpRequest, broadcasters: web::Data<Arc<Broadc
                     let msg = serde_json::to_string(&sys).unwrap();
                     format!("data: {}\n\n", msg).try_into_bytes()
                 }
+                Message::Reply(reply) => reply_event(&reply),
                 Message::ClientDisconnect => {
                     info!("Client disconnected");
-                    format!("data: Client disconnected\n\n").try_into_bytes()
+                    "data: Client disconnected\n\n".to_string().try_into_bytes()
                 }
             },
             Err(err) => {
@@ -73,6 +93,13 @@ pub async fn data_outbound(_req: HttpRequest, broadcasters: web::Data<Arc<Broadc
         }
     });
 
+    event_stream(history.chain(live))
+}
+
+fn event_stream<S>(stream: S) -> HttpResponse
+where
+    S: futures::Stream<Item = Result<actix_web::web::Bytes, String>> + 'static
+{
     HttpResponse::Ok()
         .append_header(("content-type", "text/event-stream"))
         .append_header(("cache-control", "no-cache"))
@@ -80,61 +107,159 @@ pub async fn data_outbound(_req: HttpRequest, broadcasters: web::Data<Arc<Broadc
         .streaming(stream)
 }
 
+fn application_query(query: &HashMap<String, String>) -> Option<Applicatiton> {
+    match query.get("application") {
+        Some(app_str) => {
+            serde_json::from_str(app_str)
+                .map_err(|e| error!("Failed to parse application JSON: {}", e))
+                .ok()
+        },
+        None => {
+            error!("No application parameter provided");
+            None
+        }
+    }
+}
+
+// a parameter that may be left out, but has to parse when it's given
+fn optional_query<T: FromStr>(query: &HashMap<String, String>, name: &str) -> Result<Option<T>, ()>
+where
+    T::Err: std::fmt::Display
+{
+    query.get(name).map(|value| value.parse::<T>()).transpose()
+        .map_err(|e| error!("Failed to parse {}: {}", name, e))
+}
+
+// sends a request to the client publishing the application, its replies come on the returned receiver
+async fn request(
+    broadcasters: &Broadcasters,
+    requests: &Requests,
+    application: &Applicatiton,
+    message: impl FnOnce(u64) -> SystemMessages
+) -> Option<(u64, mpsc::Receiver<ReplyMessage>)> {
+    let (mut session, publisher) = match broadcasters.lock().await.get(application) {
+        Some(broadcaster) => (broadcaster.get_session(), broadcaster.get_tx().clone()),
+        None => {
+            error!("No broadcaster found for application: {}", application.name());
+            return None;
+        }
+    };
+    let (request_id, replies) = requests.open(application.clone(), publisher).await;
+    send_system_message(&mut session, application, message(request_id)).await;
+    Some((request_id, replies))
+}
+
+// the replies to a request until the last one, or until the client goes quiet
+fn reply_stream(
+    replies: mpsc::Receiver<ReplyMessage>,
+    requests: Arc<Requests>,
+    request_id: u64,
+    timeout: Duration,
+    format: fn(&ReplyMessage) -> Result<actix_web::web::Bytes, String>
+) -> BoxStream<'static, Result<actix_web::web::Bytes, String>> {
+    stream::unfold(Some(replies), move |replies| {
+        let requests = Arc::clone(&requests);
+        async move {
+            let mut replies = replies?;
+            match time::timeout(timeout, replies.recv()).await {
+                Ok(Some(reply)) => {
+                    let next = if reply.is_last() { None } else { Some(replies) };
+                    Some((format(&reply), next))
+                }
+                Ok(None) => None,
+                Err(_) => {
+                    error!("Request {} timed out", request_id);
+                    requests.close(request_id).await;
+                    None
+                }
+            }
+        }
+    }).boxed()
+}
+
+fn reply_event(reply: &ReplyMessage) -> Result<actix_web::web::Bytes, String> {
+    trace!("Sending reply: {:#?}", reply);
+    let msg = serde_json::to_string(reply).unwrap();
+    format!("data: {}\n\n", msg).try_into_bytes()
+}
+
+// a client either names its only application in the Application header, or shares the connection
+// between applications and sends a Register message for each of them
 #[actix_web::get("/ws")]
-pub async fn data_inbound(req: HttpRequest, stream: web::Payload, broadcasters: web::Data<Arc<Broadcasters>>) -> Result<HttpResponse, Error> {
-    info!("WebSocket connection request from {}", req.peer_addr().unwrap());
-    let (res, mut session, stream) = actix_ws::handle(&req, stream)?;
-    let application = req.headers().get("Application").unwrap().to_str().unwrap().to_string();
-    let application: Applicatiton = serde_json::from_str(&application).unwrap();
+pub async fn data_inbound(req: HttpRequest, stream: web::Payload, broadcasters: web::Data<Arc<Broadcasters>>, authenticator: web::Data<Arc<Authenticator>>, requests: web::Data<Arc<Requests>>) -> Result<HttpResponse, Error> {
+    let peer = req.peer_addr().map_or_else(|| "an unknown peer".to_string(), |addr| addr.to_string());
+    info!("WebSocket connection request from {}", peer);
+    let application: Option<Applicatiton> = match req.headers().get("Application") {
+        Some(application) => {
+            let application = application.to_str().ok()
+                .and_then(|application| serde_json::from_str(application).ok());
+            match application {
+                Some(application) => Some(application),
+                None => {
+                    error!("Rejecting WebSocket connection from {}: invalid Application header", peer);
+                    return Ok(HttpResponse::BadRequest().body("invalid Application header"));
+                }
+            }
+        }
+        None => None,
+    };
+
+    let token = req.headers().get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(auth::bearer_token)
+        .map(String::from);
+    let certificate = req.conn_data::<ClientCertificate>().cloned();
+    // checked before the upgrade, a shared connection still needs a valid token before it may register
+    // anything, each application it registers is checked again
+    let rejection = match &application {
+        Some(application) => authenticator.authorize(token.as_deref(), certificate.as_ref(), application)
+            .map_err(|e| (format!(" for application {}", application.name()), e)),
+        None => authenticator.authenticate(token.as_deref(), certificate.as_ref())
+            .map_err(|e| (String::new(), e)),
+    };
+
+    let (mut res, mut session, stream) = actix_ws::handle(&req, stream)?;
+    if let Err((target, e)) = rejection {
+        error!("Rejecting WebSocket connection{}: {}", target, e);
+        let reason = CloseReason { code: CloseCode::Policy, description: Some(e.to_string()) };
+        let _ = session.close(Some(reason)).await;
+        return Ok(res);
+    }
+
+    let offered_encoding = req.headers().get(WIRE_ENCODING_HEADER).and_then(|value| value.to_str().ok());
+    let encoding = WireEncoding::negotiate(offered_encoding);
+    res.headers_mut().insert(
+        HeaderName::from_static("wire-encoding"),
+        HeaderValue::from_str(&encoding.header_value()).map_err(actix_web::error::ErrorInternalServerError)?
+    );
+    info!("Negotiated wire encoding {} for {}", encoding.header_value(), peer);
     
     let mut stream = stream
     .aggregate_continuations()
     // aggregate continuation frames up to 1MiB
-    .max_continuation_size(2_usize.pow(20));
+    .max_continuation_size(MAX_FRAME_SIZE);
     
-    info!("WebSocket connection established for application: {}", application.name());
-
-    let (tx, _) = broadcast::channel(100);
-
-    let mut locked_broadcasters = broadcasters.lock().await;
-    locked_broadcasters.insert(application.clone(), tx.clone());
-    drop(locked_broadcasters);
-
-    let start_message = Message::System(SystemMessage::new(application.clone(), SystemMessages::Start));
-    session.text(serde_json::to_string(&start_message).unwrap()).await.unwrap();
-
-    let app = application.clone();
+    let inbound = Arc::new(Inbound {
+        broadcasters: Arc::clone(&broadcasters),
+        authenticator: Arc::clone(&authenticator),
+        requests: Arc::clone(&requests),
+        token,
+        certificate,
+        registrations: Mutex::new(BTreeMap::new())
+    });
+    if let Some(application) = application {
+        info!("WebSocket connection established for application: {}", application.name());
+        inbound.register(application, &mut session).await;
+    } else {
+        info!("Shared WebSocket connection established");
+    }
 
+    let handler_inbound = Arc::clone(&inbound);
     let mut ping_session = session.clone();
     let handle = rt::spawn(async move {
         while let Some(msg) = stream.recv().await {
-            if tx.receiver_count() > 0 {
-                match handle_message(msg, &mut session, &tx).await {
-                    false => break,
-                    _ => {
-                        continue;
-                    }
-                }
-            }
-
-            let pause_message = Message::System(SystemMessage::new(application.clone(), SystemMessages::Pause));
-            session.text(serde_json::to_string(&pause_message).unwrap()).await.unwrap();
-            loop {
-                if tx.receiver_count() > 0 {
-                    // Consume any pending messages in the stream buffer
-                    while let Ok(msg) = tokio::time::timeout(
-                        Duration::from_millis(50), 
-                        stream.recv()
-                    ).await {
-                        info!("Consuming pending message: {:?}", msg);
-                        continue;
-                    }
-
-                    let resume_message = Message::System(SystemMessage::new(application.clone(), SystemMessages::Resume));
-                    session.text(serde_json::to_string(&resume_message).unwrap()).await.unwrap();
-                    break;
-                }
-                sleep(Duration::from_secs(1)).await;
+            if !handle_message(msg, &mut session, &handler_inbound, &encoding).await {
+                break;
             }
         }
         info!("webSocket connection closed");
@@ -144,14 +269,12 @@ pub async fn data_inbound(req: HttpRequest, stream: web::Payload, broadcasters:
         let ping_interval = Duration::from_secs(1);
         
         while let Ok(()) = ping_session.ping(b"ping").await {
+            inbound.resume_watched(&mut ping_session).await;
             sleep(ping_interval).await;
         }
         
         info!("Ping failed, aborting message handler");
-        let mut locked_broadcasters = broadcasters.lock().await;
-        let rx = locked_broadcasters.remove(&app).unwrap();
-        drop(locked_broadcasters);
-        let _ = rx.send(Message::ClientDisconnect);
+        inbound.unregister_all().await;
         handle.abort();
         info!("WebSocket connection terminated by ping monitor");
     });
@@ -159,39 +282,188 @@ pub async fn data_inbound(req: HttpRequest, stream: web::Payload, broadcasters:
     Ok(res)
 }
 
-async fn handle_message(msg: Result<AggregatedMessage, ProtocolError>, session: &mut Session, tx: &Sender<Message>) -> bool {
-    match msg {
+struct Registration {
+    tx: Sender<Message>,
+    // Pause was sent because no one was watching, Resume follows once someone is
+    paused: bool
+}
+
+// the applications of one client connection
+struct Inbound {
+    broadcasters: Arc<Broadcasters>,
+    authenticator: Arc<Authenticator>,
+    requests: Arc<Requests>,
+    token: Option<String>,
+    certificate: Option<ClientCertificate>,
+    registrations: Mutex<BTreeMap<Applicatiton, Registration>>
+}
+
+impl Inbound {
+    async fn register(&self, application: Applicatiton, session: &mut Session) {
+        if let Err(e) = self.authenticator.authorize(self.token.as_deref(), self.certificate.as_ref(), &application) {
+            error!("Rejecting application {}: {}", application.name(), e);
+            send_system_message(session, &application, SystemMessages::Error(e.to_string())).await;
+            return;
+        }
+
+        // a client that restarted takes over from its connection that isn't gone yet, anyone else is refused
+        let credential = (self.token.clone(), self.certificate.clone());
+        let (tx, _) = broadcast::channel(100);
+        let mut locked_broadcasters = self.broadcasters.lock().await;
+        if let Some(current) = locked_broadcasters.get(&application) {
+            if *current.get_credential() != credential {
+                drop(locked_broadcasters);
+                error!("Rejecting application {}: another client publishes it", application.name());
+                let reason = format!("{} is already published by another client", application.name());
+                send_system_message(session, &application, SystemMessages::Error(reason)).await;
+                return;
+            }
+            warn!("Application {} registered again, it replaces the earlier registration", application.name());
+        }
+        locked_broadcasters.insert(application.clone(), Broadcaster::new(tx.clone(), session.clone(), credential));
+        drop(locked_broadcasters);
+        // a new channel has no viewers yet, the client keeps its lines until one opens the application
+        self.registrations.lock().await.insert(application.clone(), Registration { tx, paused: true });
+
+        info!("Registered application: {}", application.name());
+        send_system_message(session, &application, SystemMessages::Start).await;
+        send_system_message(session, &application, SystemMessages::Pause).await;
+    }
+
+    // the client is done with the application, viewers still get its Stop
+    async fn stop(&self, application: &Applicatiton, stop_message: Message) {
+        if let Some(registration) = self.registrations.lock().await.remove(application) {
+            if registration.tx.receiver_count() > 0 {
+                broadcast_message(stop_message, &registration.tx);
+            }
+            remove_broadcaster(&self.broadcasters, application, &registration.tx).await;
+            info!("Unregistered application: {}", application.name());
+        }
+    }
+
+    // only applications registered on this connection can answer, and only requests sent to it
+    async fn reply(&self, reply: ReplyMessage) {
+        let publisher = match self.registrations.lock().await.get(reply.application()) {
+            Some(registration) => registration.tx.clone(),
+            None => {
+                error!("Dropping reply of unregistered application: {}", reply.application().name());
+                return;
+            }
+        };
+        self.requests.reply(reply, &publisher).await;
+    }
+
+    // kept with the broadcaster, so they go away once the client stops publishing the application
+    async fn record_stats(&self, application: &Applicatiton, stats: TailerStats) {
+        let tx = match self.registrations.lock().await.get(application) {
+            Some(registration) => registration.tx.clone(),
+            None => {
+                error!("Dropping stats of unregistered application: {}", application.name());
+                return;
+            }
+        };
+        let mut locked_broadcasters = self.broadcasters.lock().await;
+        if let Some(broadcaster) = locked_broadcasters.get_mut(application).filter(|current| current.get_tx().same_channel(&tx)) {
+            broadcaster.set_stats(ApplicationStats::new(application.clone(), stats));
+        }
+    }
+
+    async fn unregister_all(&self) {
+        let registrations = std::mem::take(&mut *self.registrations.lock().await);
+        for (application, registration) in registrations {
+            remove_broadcaster(&self.broadcasters, &application, &registration.tx).await;
+        }
+    }
+
+    // asks the client to hold an application's messages while no one is watching it
+    async fn broadcast(&self, message: Message, session: &mut Session) {
+        let application = match message.application() {
+            Some(application) => application.clone(),
+            None => return,
+        };
+        let mut registrations = self.registrations.lock().await;
+        let registration = match registrations.get_mut(&application) {
+            Some(registration) => registration,
+            None => {
+                error!("Dropping message of unregistered application: {}", application.name());
+                return;
+            }
+        };
+        if registration.tx.receiver_count() > 0 {
+            broadcast_message(message, &registration.tx);
+        } else if !registration.paused {
+            registration.paused = true;
+            drop(registrations);
+            send_system_message(session, &application, SystemMessages::Pause).await;
+        }
+    }
+
+    async fn resume_watched(&self, session: &mut Session) {
+        let mut resumed = vec![];
+        for (application, registration) in self.registrations.lock().await.iter_mut() {
+            if registration.paused && registration.tx.receiver_count() > 0 {
+                registration.paused = false;
+                resumed.push(application.clone());
+            }
+        }
+        for application in resumed {
+            send_system_message(session, &application, SystemMessages::Resume).await;
+        }
+    }
+}
+
+// a newer connection may have registered the application again, its broadcaster is left alone
+async fn remove_broadcaster(broadcasters: &Broadcasters, application: &Applicatiton, tx: &Sender<Message>) {
+    let mut locked_broadcasters = broadcasters.lock().await;
+    if locked_broadcasters.get(application).is_some_and(|current| current.get_tx().same_channel(tx)) {
+        locked_broadcasters.remove(application);
+    }
+    drop(locked_broadcasters);
+    let _ = tx.send(Message::ClientDisconnect);
+}
+
+async fn send_system_message(session: &mut Session, application: &Applicatiton, message: SystemMessages) {
+    let message = Message::System(SystemMessage::new(application.clone(), message));
+    if let Err(e) = session.text(serde_json::to_string(&message).unwrap()).await {
+        error!("Error sending system message: {}", e);
+    }
+}
+
+async fn handle_message(msg: Result<AggregatedMessage, ProtocolError>, session: &mut Session, inbound: &Inbound, encoding: &WireEncoding) -> bool {
+    let message = match msg {
         Ok(AggregatedMessage::Text(text)) => {
-            // echo text message
+            // text frames are always json, whatever was negotiated
             let message: Result<Message, serde_json::Error> = serde_json::from_str(&text);
             match message {
-                Ok(message) => {
-                    info!("Received message: {:#?}", message);
-                    match tx.send(message) {
-                        Ok(n) => trace!("message broadcasted to {} subscribers", n),
-                        Err(err) => error!("Error broadcasting message: {:?}", err),
-                    }
-                }
+                Ok(message) => message,
                 Err(e) => {
                     error!("Failed to parse message: {:?}", e);
+                    return true;
                 }
             }
         }
         
         Ok(AggregatedMessage::Binary(bin)) => {
-            // echo binary message
-            session.binary(bin).await.unwrap();
+            // binary frames carry messages in the negotiated wire encoding
+            match encoding.decode(&bin) {
+                Ok(message) => message,
+                Err(e) => {
+                    error!("Failed to decode binary message: {}", e);
+                    return true;
+                }
+            }
         }
         
         Ok(AggregatedMessage::Ping(msg)) => {
             // respond to PING frame with PONG frame
             session.pong(&msg).await.unwrap();
+            return true;
         }
         
         Ok(AggregatedMessage::Close(reason)) => {
             // close the session
-            if reason.is_some() {
-                info!("Closing session with reason code: {:?} and description: {:?}", reason.clone().unwrap().code, reason.unwrap().description);
+            if let Some(reason) = reason {
+                info!("Closing session with reason code: {:?} and description: {:?}", reason.code, reason.description);
             } else {
                 error!("Closing session without reason");
             }
@@ -199,8 +471,167 @@ async fn handle_message(msg: Result<AggregatedMessage, ProtocolError>, session:
             return false;
         }
 
-        _ => {}
+        _ => return true,
+    };
+
+    // replies only go to whoever asked for them
+    let message = match message {
+        Message::Reply(reply) => {
+            inbound.reply(reply).await;
+            return true;
+        }
+        message => message,
+    };
+
+    match (message.system().map(SystemMessage::message), message.application()) {
+        (Some(SystemMessages::Register), Some(application)) => {
+            let application = application.clone();
+            inbound.register(application, session).await;
+        }
+        (Some(SystemMessages::Stop), Some(application)) => {
+            let application = application.clone();
+            inbound.stop(&application, message).await;
+        }
+        // for the stats api, not for viewers
+        (Some(SystemMessages::Stats(stats)), Some(application)) => {
+            inbound.record_stats(application, stats.clone()).await;
+        }
+        _ => inbound.broadcast(message, session).await,
     }
 
     true
-}
\ No newline at end of file
+}
+
+fn broadcast_message(message: Message, tx: &Sender<Message>) {
+    info!("Received message: {:#?}", message);
+    match tx.send(message) {
+        Ok(n) => trace!("message broadcasted to {} subscribers", n),
+        Err(err) => error!("Error broadcasting message: {:?}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, HttpServer};
+    use tungstenite::{client::IntoClientRequest, protocol::frame::coding::CloseCode as WsCloseCode, Message as WsMessage};
+
+    use super::*;
+
+    async fn serve(authenticator: Authenticator) -> u16 {
+        let broadcasters = Arc::new(broadcaster::new_broadcasters());
+        let authenticator = Arc::new(authenticator);
+        let requests = Arc::new(Requests::new());
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(Arc::clone(&broadcasters)))
+                .app_data(web::Data::new(Arc::clone(&authenticator)))
+                .app_data(web::Data::new(Arc::clone(&requests)))
+                .service(data_inbound)
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let port = server.addrs()[0].port();
+        rt::spawn(server.run());
+        port
+    }
+
+    type Socket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+    // connects as the application, or as a shared connection without one
+    async fn connect(port: u16, application: Option<&str>, token: Option<&str>) -> Socket {
+        let mut request = format!("ws://127.0.0.1:{}/ws", port).into_client_request().unwrap();
+        if let Some(application) = application {
+            let application = serde_json::to_string(&Applicatiton::SinglePod(application.to_string())).unwrap();
+            request.headers_mut().insert("Application", application.parse().unwrap());
+        }
+        if let Some(token) = token {
+            request.headers_mut().insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        }
+        tokio_tungstenite::connect_async(request).await.unwrap().0
+    }
+
+    async fn next_frame(socket: &mut Socket) -> WsMessage {
+        loop {
+            match socket.next().await.unwrap().unwrap() {
+                WsMessage::Ping(_) => continue,
+                frame => return frame,
+            }
+        }
+    }
+
+    // the first frame the server sends
+    async fn first_frame(port: u16, application: Option<&str>, token: Option<&str>) -> WsMessage {
+        let mut socket = connect(port, application, token).await;
+        let frame = next_frame(&mut socket).await;
+        let _ = socket.close(None).await;
+        frame
+    }
+
+    fn system_message(frame: WsMessage) -> SystemMessages {
+        match frame {
+            WsMessage::Text(text) => match serde_json::from_str(&text).unwrap() {
+                Message::System(message) => message.message().clone(),
+                message => panic!("expected a system message, got {:?}", message),
+            },
+            frame => panic!("expected a system message, got {:?}", frame),
+        }
+    }
+
+    #[actix_web::test]
+    async fn unauthorized_client_is_closed_with_policy() {
+        let port = serve(Authenticator::new(vec!["secret".to_string()], None, None)).await;
+
+        for application in [Some("app"), None] {
+            for token in [None, Some("wrong")] {
+                match first_frame(port, application, token).await {
+                    WsMessage::Close(Some(frame)) => assert_eq!(frame.code, WsCloseCode::Policy),
+                    frame => panic!("expected a policy close, got {:?}", frame),
+                }
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn authorized_client_is_started() {
+        let port = serve(Authenticator::new(vec!["secret".to_string()], None, None)).await;
+
+        match first_frame(port, Some("app"), Some("secret")).await {
+            WsMessage::Text(text) => match serde_json::from_str(&text).unwrap() {
+                Message::System(message) => assert!(matches!(message.message(), SystemMessages::Start)),
+                message => panic!("expected a start message, got {:?}", message),
+            },
+            frame => panic!("expected a start message, got {:?}", frame),
+        }
+    }
+
+    #[actix_web::test]
+    async fn invalid_application_header_is_a_bad_request() {
+        let port = serve(Authenticator::new(vec![], None, None)).await;
+
+        for application in ["not json", "{\"Unknown\": \"app\"}"] {
+            let mut request = format!("ws://127.0.0.1:{}/ws", port).into_client_request().unwrap();
+            request.headers_mut().insert("Application", application.parse().unwrap());
+            match tokio_tungstenite::connect_async(request).await {
+                Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 400),
+                result => panic!("expected a bad request, got {:?}", result.map(|(_, response)| response)),
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn only_the_same_client_takes_over_a_live_application() {
+        let port = serve(Authenticator::new(vec!["one".to_string(), "two".to_string()], None, None)).await;
+
+        let mut first = connect(port, Some("app"), Some("one")).await;
+        assert_eq!(system_message(next_frame(&mut first).await), SystemMessages::Start);
+
+        match system_message(first_frame(port, Some("app"), Some("two")).await) {
+            SystemMessages::Error(reason) => assert!(reason.contains("already published"), "{}", reason),
+            message => panic!("expected an error, got {:?}", message),
+        }
+        // e.g. the same client restarted before its old connection was noticed to be gone
+        assert_eq!(system_message(first_frame(port, Some("app"), Some("one")).await), SystemMessages::Start);
+        let _ = first.close(None).await;
+    }
+}
@@ -0,0 +1,98 @@
+use std::{collections::BTreeMap, sync::atomic::{AtomicU64, Ordering}};
+
+use log::{debug, warn};
+use tokio::sync::{broadcast, mpsc, Mutex};
+
+use crate::{message::{Message, ReplyMessage}, Applicatiton};
+
+const REPLY_BUFFER: usize = 256;
+
+// requests sent to clients that are waiting for replies, by request id
+pub struct Requests {
+    next_id: AtomicU64,
+    pending: Mutex<BTreeMap<u64, Pending>>
+}
+
+// only the connection the request was sent to can answer it, for the application it was about
+struct Pending {
+    application: Applicatiton,
+    // the broadcaster channel of the application on that connection
+    publisher: broadcast::Sender<Message>,
+    replies: mpsc::Sender<ReplyMessage>
+}
+
+impl Default for Requests {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Requests {
+    pub fn new() -> Self {
+        Self { next_id: AtomicU64::new(1), pending: Mutex::new(BTreeMap::new()) }
+    }
+
+    // a new request id for the application published on the broadcaster channel, and where its replies will arrive
+    pub async fn open(&self, application: Applicatiton, publisher: broadcast::Sender<Message>) -> (u64, mpsc::Receiver<ReplyMessage>) {
+        let request_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (replies, rx) = mpsc::channel(REPLY_BUFFER);
+        self.pending.lock().await.insert(request_id, Pending { application, publisher, replies });
+        (request_id, rx)
+    }
+
+    // hands a reply that came from the application's broadcaster channel to whoever asked, a request is
+    // forgotten after its last reply or once no one waits for it
+    pub async fn reply(&self, reply: ReplyMessage, publisher: &broadcast::Sender<Message>) {
+        let request_id = reply.request_id();
+        let tx = match self.pending.lock().await.get(&request_id) {
+            Some(pending) if pending.application == *reply.application() && pending.publisher.same_channel(publisher) => pending.replies.clone(),
+            Some(_) => {
+                warn!("Dropping reply to request {} from a client it wasn't sent to", request_id);
+                return;
+            }
+            None => {
+                debug!("Dropping reply to unknown request {}", request_id);
+                return;
+            }
+        };
+        let last = reply.is_last();
+        if tx.send(reply).await.is_err() || last {
+            self.close(request_id).await;
+        }
+    }
+
+    // no one waits for the request anymore, replies that still come are dropped
+    pub async fn close(&self, request_id: u64) {
+        self.pending.lock().await.remove(&request_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::Replies;
+
+    use super::*;
+
+    fn application(name: &str) -> Applicatiton {
+        Applicatiton::SinglePod(name.to_string())
+    }
+
+    #[tokio::test]
+    async fn replies_only_come_from_where_the_request_went() {
+        let requests = Requests::new();
+        let (publisher, _) = broadcast::channel(1);
+        let (other_publisher, _) = broadcast::channel(1);
+        let (request_id, mut replies) = requests.open(application("app"), publisher.clone()).await;
+
+        requests.reply(ReplyMessage::new(application("app"), request_id, Replies::History("spoofed".to_string())), &other_publisher).await;
+        requests.reply(ReplyMessage::new(application("other"), request_id, Replies::History("spoofed".to_string())), &publisher).await;
+        requests.reply(ReplyMessage::new(application("app"), request_id, Replies::History("line".to_string())), &publisher).await;
+        requests.reply(ReplyMessage::new(application("app"), request_id, Replies::Done), &publisher).await;
+        // the request was forgotten after its last reply
+        requests.reply(ReplyMessage::new(application("app"), request_id, Replies::History("late".to_string())), &publisher).await;
+
+        assert_eq!(replies.recv().await.unwrap().reply(), &Replies::History("line".to_string()));
+        assert_eq!(replies.recv().await.unwrap().reply(), &Replies::Done);
+        assert!(replies.recv().await.is_none());
+    }
+}
@@ -0,0 +1,58 @@
+use std::{collections::HashMap, sync::Arc};
+
+use actix_web::{get, web, HttpResponse, Responder};
+use chrono::NaiveDateTime;
+use futures::stream;
+use log::{error, info};
+
+use crate::message::SystemMessages;
+
+use super::{application_query, broadcaster::Broadcasters, event_stream, optional_query, reply_event, reply_stream, request, requests::Requests, SCAN_REPLY_TIMEOUT};
+
+// greps the files of the client publishing the application, or with name= instead of application=
+// those of every pod of the application, the matches come as Reply events and each client ends with Done
+#[get("/api/search")]
+pub async fn search(broadcasters: web::Data<Arc<Broadcasters>>, requests: web::Data<Arc<Requests>>, query: web::Query<HashMap<String, String>>) -> impl Responder {
+    let pattern = match query.get("pattern") {
+        Some(pattern) => pattern.clone(),
+        None => {
+            error!("No pattern parameter provided");
+            return HttpResponse::BadRequest().finish();
+        }
+    };
+    let (from, to, limit) = match (
+        optional_query::<NaiveDateTime>(&query, "from"),
+        optional_query::<NaiveDateTime>(&query, "to"),
+        optional_query::<usize>(&query, "limit")
+    ) {
+        (Ok(from), Ok(to), Ok(limit)) => (from, to, limit),
+        _ => return HttpResponse::BadRequest().finish(),
+    };
+
+    let applications = match query.get("name") {
+        Some(name) => broadcasters.lock().await.keys()
+            .filter(|application| application.name() == *name)
+            .cloned()
+            .collect(),
+        None => match application_query(&query) {
+            Some(application) => vec![application],
+            None => return HttpResponse::BadRequest().finish(),
+        },
+    };
+
+    let mut results = vec![];
+    for application in applications {
+        let pattern = pattern.clone();
+        let search = |request_id| SystemMessages::Search { request_id, pattern, from, to, limit };
+        if let Some((request_id, replies)) = request(&broadcasters, &requests, &application, search).await {
+            info!("Requested search {} of application: {}", request_id, application.name());
+            results.push(reply_stream(replies, Arc::clone(&requests), request_id, SCAN_REPLY_TIMEOUT, reply_event));
+        }
+    }
+    if results.is_empty() {
+        error!("No client to search");
+        return HttpResponse::BadRequest().finish();
+    }
+
+    event_stream(stream::select_all(results))
+}
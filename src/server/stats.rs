@@ -0,0 +1,45 @@
+use std::{collections::HashMap, sync::Arc};
+
+use actix_web::{get, web, HttpResponse, Responder};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::{message::TailerStats, Applicatiton};
+
+use super::{application_query, broadcaster::Broadcasters};
+
+// the last stats a client sent for an application, and when they came
+#[derive(Debug, Serialize, Clone)]
+pub struct ApplicationStats {
+    application: Applicatiton,
+    received: NaiveDateTime,
+    #[serde(flatten)]
+    stats: TailerStats
+}
+
+impl ApplicationStats {
+    pub fn new(application: Applicatiton, stats: TailerStats) -> Self {
+        Self { application, received: chrono::Utc::now().naive_utc(), stats }
+    }
+}
+
+// the stats of every application published right now, of one with application= or of every pod of
+// one with name=, applications whose client hasn't sent any yet are left out
+#[get("/api/stats")]
+pub async fn get_stats(broadcasters: web::Data<Arc<Broadcasters>>, query: web::Query<HashMap<String, String>>) -> impl Responder {
+    let application = match query.get("application") {
+        Some(_) => match application_query(&query) {
+            Some(application) => Some(application),
+            None => return HttpResponse::BadRequest().finish(),
+        },
+        None => None,
+    };
+    let name = query.get("name");
+
+    let stats: Vec<ApplicationStats> = broadcasters.lock().await.iter()
+        .filter(|(published, _)| application.as_ref().is_none_or(|application| application == *published))
+        .filter(|(published, _)| name.is_none_or(|name| published.name() == *name))
+        .filter_map(|(_, broadcaster)| broadcaster.get_stats().cloned())
+        .collect();
+    HttpResponse::Ok().json(stats)
+}
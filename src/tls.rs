@@ -0,0 +1,202 @@
+use std::{fs::File, io::{self, BufReader}, sync::Arc};
+
+use log::warn;
+use rustls::{crypto::CryptoProvider, pki_types::{CertificateDer, PrivateKeyDer}, server::WebPkiClientVerifier, ClientConfig, RootCertStore, ServerConfig};
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+pub fn load_certificates(path: &str) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certificates = rustls_pemfile::certs(&mut reader).collect::<io::Result<Vec<_>>>()?;
+    if certificates.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("no certificate found in {}", path)));
+    }
+    Ok(certificates)
+}
+
+pub fn load_private_key(path: &str) -> io::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("no private key found in {}", path)))
+}
+
+fn root_store(ca_file: &str) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for certificate in load_certificates(ca_file)? {
+        roots.add(certificate).map_err(io::Error::other)?;
+    }
+    Ok(roots)
+}
+
+// with a client ca, client certificates signed by it are verified but not required,
+// browsers still reach the ui and it's up to each endpoint to insist on a certificate
+pub fn server_config(cert_path: &str, key_path: &str, client_ca_path: Option<&str>) -> io::Result<ServerConfig> {
+    let certificates = load_certificates(cert_path)?;
+    let key = load_private_key(key_path)?;
+
+    let builder = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?;
+    let builder = match client_ca_path {
+        Some(client_ca_path) => {
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(root_store(client_ca_path)?), provider())
+                .allow_unauthenticated()
+                .build()
+                .map_err(io::Error::other)?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    builder
+        .with_single_cert(certificates, key)
+        .map_err(io::Error::other)
+}
+
+// without a ca bundle the server certificate is checked against the system trust store
+pub fn client_config(ca_file: Option<&str>, client_identity: Option<(&str, &str)>) -> io::Result<ClientConfig> {
+    let roots = match ca_file {
+        Some(ca_file) => root_store(ca_file)?,
+        None => {
+            let mut roots = RootCertStore::empty();
+            let native_certificates = rustls_native_certs::load_native_certs();
+            for error in native_certificates.errors {
+                warn!("Failed to load a native certificate: {}", error);
+            }
+            roots.add_parsable_certificates(native_certificates.certs);
+            roots
+        }
+    };
+
+    let builder = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_root_certificates(roots);
+
+    match client_identity {
+        Some((cert_path, key_path)) => builder
+            .with_client_auth_cert(load_certificates(cert_path)?, load_private_key(key_path)?)
+            .map_err(io::Error::other),
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{SinkExt, StreamExt};
+    use rustls::{pki_types::ServerName, ClientConnection, Connection, ServerConnection};
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+    use tokio_tungstenite::{connect_async_tls_with_config, Connector};
+    use tungstenite::Message;
+
+    use super::*;
+
+    // a test ca, and a server certificate for localhost and a client certificate signed by it
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn connect(client: ClientConfig, server: ServerConfig) -> Result<ServerConnection, rustls::Error> {
+        let client = ClientConnection::new(Arc::new(client), ServerName::try_from("localhost").unwrap())?;
+        let server = ServerConnection::new(Arc::new(server))?;
+        let (mut client, mut server) = (Connection::from(client), Connection::from(server));
+        while client.is_handshaking() || server.is_handshaking() {
+            transfer(&mut client, &mut server)?;
+            transfer(&mut server, &mut client)?;
+        }
+        match server {
+            Connection::Server(server) => Ok(server),
+            Connection::Client(_) => unreachable!(),
+        }
+    }
+
+    fn transfer(from: &mut Connection, to: &mut Connection) -> Result<(), rustls::Error> {
+        let mut records = Vec::new();
+        while from.wants_write() {
+            from.write_tls(&mut records).unwrap();
+        }
+        let mut records = records.as_slice();
+        while !records.is_empty() {
+            to.read_tls(&mut records).unwrap();
+            to.process_new_packets()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_with_the_server_certificate() {
+        let server = server_config(&fixture("server.pem"), &fixture("server.key"), None).unwrap();
+        let client = client_config(Some(&fixture("ca.pem")), None).unwrap();
+
+        let server = connect(client, server).unwrap();
+        assert!(server.peer_certificates().is_none());
+    }
+
+    #[test]
+    fn untrusted_server_certificate_is_rejected() {
+        let server = server_config(&fixture("server.pem"), &fixture("server.key"), None).unwrap();
+        // a certificate that isn't a ca, so nothing it's given to trust signed the server's
+        let client = client_config(Some(&fixture("server.pem")), None).unwrap();
+
+        assert!(connect(client, server).is_err());
+    }
+
+    #[test]
+    fn client_certificate_is_verified() {
+        let server = server_config(&fixture("server.pem"), &fixture("server.key"), Some(&fixture("ca.pem"))).unwrap();
+        let client = client_config(Some(&fixture("ca.pem")), Some((&fixture("client.pem"), &fixture("client.key")))).unwrap();
+
+        let server = connect(client, server).unwrap();
+        let certificates = server.peer_certificates().unwrap();
+        assert_eq!(certificates[0], load_certificates(&fixture("client.pem")).unwrap()[0]);
+    }
+
+    #[test]
+    fn client_certificate_is_optional() {
+        let server = server_config(&fixture("server.pem"), &fixture("server.key"), Some(&fixture("ca.pem"))).unwrap();
+        let client = client_config(Some(&fixture("ca.pem")), None).unwrap();
+
+        let server = connect(client, server).unwrap();
+        assert!(server.peer_certificates().is_none());
+    }
+
+    #[test]
+    fn untrusted_client_certificate_is_rejected() {
+        let server = server_config(&fixture("server.pem"), &fixture("server.key"), Some(&fixture("server.pem"))).unwrap();
+        let client = client_config(Some(&fixture("ca.pem")), Some((&fixture("client.pem"), &fixture("client.key")))).unwrap();
+
+        assert!(connect(client, server).is_err());
+    }
+
+    #[test]
+    fn missing_key_is_an_error() {
+        assert!(server_config(&fixture("server.pem"), &fixture("ca.pem"), None).is_err());
+        assert!(server_config(&fixture("missing.pem"), &fixture("server.key"), None).is_err());
+        assert!(client_config(Some(&fixture("server.key")), None).is_err());
+        assert!(client_config(Some(&fixture("ca.pem")), Some((&fixture("client.pem"), &fixture("ca.pem")))).is_err());
+    }
+
+    #[tokio::test]
+    async fn websocket_over_tls() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config(&fixture("server.pem"), &fixture("server.key"), None).unwrap()));
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let stream = acceptor.accept(stream).await.unwrap();
+            let mut websocket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let msg = websocket.next().await.unwrap().unwrap();
+            websocket.send(msg).await.unwrap();
+        });
+
+        let connector = Connector::Rustls(Arc::new(client_config(Some(&fixture("ca.pem")), None).unwrap()));
+        let uri = format!("wss://localhost:{}/ws", port);
+        let (mut websocket, _) = connect_async_tls_with_config(uri, None, false, Some(connector)).await.unwrap();
+        websocket.send(Message::Text("hello".to_string())).await.unwrap();
+        assert_eq!(websocket.next().await.unwrap().unwrap(), Message::Text("hello".to_string()));
+        server.await.unwrap();
+    }
+}